@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "expires_at" timestamp with time zone;
+CREATE INDEX IF NOT EXISTS expires_at_index ON "pastebin" ("expires_at") WHERE "expires_at" IS NOT NULL;
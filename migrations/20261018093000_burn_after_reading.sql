@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "burn_after_reading" boolean DEFAULT false NOT NULL;
+
+-- Burnable pastes carry an unguessable suffix in their S3 key
+ALTER TABLE "pastebin" ALTER COLUMN "s3_key" TYPE varchar(128);
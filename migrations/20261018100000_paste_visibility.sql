@@ -0,0 +1,16 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "visibility" varchar(8) DEFAULT 'public' NOT NULL;
+
+-- Only public pastes make it into the tag cloud
+DROP MATERIALIZED VIEW IF EXISTS popular_tags;
+CREATE MATERIALIZED VIEW IF NOT EXISTS popular_tags AS
+SELECT tag, COUNT(*) AS frequency
+FROM (
+   SELECT unnest(tags) AS tag
+   FROM pastebin
+   WHERE visibility = 'public'
+) AS flattened_tags
+GROUP BY tag
+ORDER BY frequency DESC, tag;
+
+CREATE UNIQUE INDEX ON popular_tags(tag);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "password_hash" varchar(128);
@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "revision" integer DEFAULT 0 NOT NULL;
+
+CREATE TABLE IF NOT EXISTS "paste_revisions" (
+    "id" SERIAL PRIMARY KEY,
+    "paste_id" varchar(12) NOT NULL REFERENCES "pastebin" ("paste_id") ON DELETE CASCADE ON UPDATE CASCADE,
+    "revision" integer NOT NULL,
+    "s3_key" varchar(128) NOT NULL,
+    "s3_content_length" integer NOT NULL,
+    "date" timestamp with time zone NOT NULL,
+    UNIQUE ("paste_id", "revision")
+);
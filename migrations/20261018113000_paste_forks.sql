@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "forked_from" varchar(12) NULL
+    REFERENCES "pastebin" ("paste_id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+CREATE INDEX IF NOT EXISTS "pastebin_forked_from_idx" ON "pastebin" ("forked_from") WHERE "forked_from" IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ALTER COLUMN "paste_id" TYPE varchar(64);
+ALTER TABLE "pastebin" ALTER COLUMN "forked_from" TYPE varchar(64);
+ALTER TABLE "paste_revisions" ALTER COLUMN "paste_id" TYPE varchar(64);
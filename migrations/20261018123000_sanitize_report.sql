@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "sanitize_report" text[] NULL;
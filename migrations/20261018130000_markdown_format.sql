@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ALTER COLUMN "format" TYPE varchar(16);
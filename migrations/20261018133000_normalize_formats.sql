@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Unknown formats used to be stored as they came in and read back as plain text
+UPDATE "pastebin" SET "format" = 'plain'
+WHERE "format" NOT IN ('plain', 'html', 'log', 'md', 'enc', 'code')
+    AND "format" NOT IN ('code:js', 'code:lua', 'code:python', 'code:rust');
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS "paste_files" (
+    "id" SERIAL PRIMARY KEY,
+    "paste_id" varchar(64) NOT NULL REFERENCES "pastebin" ("paste_id") ON DELETE CASCADE ON UPDATE CASCADE,
+    "position" integer NOT NULL,
+    "name" varchar(64) NOT NULL,
+    "format" varchar(16) NOT NULL,
+    "s3_key" varchar(128) NOT NULL,
+    "s3_content_length" integer NOT NULL,
+    UNIQUE ("paste_id", "position")
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS "paste_attachments" (
+    "id" SERIAL PRIMARY KEY,
+    "paste_id" varchar(64) NOT NULL REFERENCES "pastebin" ("paste_id") ON DELETE CASCADE ON UPDATE CASCADE,
+    "position" integer NOT NULL,
+    "content_type" varchar(32) NOT NULL,
+    "s3_key" varchar(128) NOT NULL,
+    "s3_content_length" integer NOT NULL,
+    "date" timestamp with time zone NOT NULL,
+    UNIQUE ("paste_id", "position")
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "content_encoding" varchar(16) NOT NULL DEFAULT 'identity';
+ALTER TABLE "paste_revisions" ADD COLUMN IF NOT EXISTS "content_encoding" varchar(16) NOT NULL DEFAULT 'identity';
+ALTER TABLE "paste_files" ADD COLUMN IF NOT EXISTS "content_encoding" varchar(16) NOT NULL DEFAULT 'identity';
+
+-- Up until now brotli was the only encoding and the S3 key said so
+UPDATE "pastebin" SET "content_encoding" = 'br' WHERE "s3_key" LIKE '%.br';
+UPDATE "paste_revisions" SET "content_encoding" = 'br' WHERE "s3_key" LIKE '%.br';
+UPDATE "paste_files" SET "content_encoding" = 'br' WHERE "s3_key" LIKE '%.br';
@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE "pastebin" ADD COLUMN IF NOT EXISTS "content_hash" varchar(64);
+
+-- Objects shared by pastes with identical content, gone once nothing points at them
+CREATE TABLE IF NOT EXISTS "blobs" (
+    "s3_key" varchar(128) PRIMARY KEY,
+    "content_hash" varchar(64) NOT NULL,
+    "s3_content_length" integer NOT NULL,
+    "refcount" integer NOT NULL,
+    "date" timestamp with time zone NOT NULL
+);
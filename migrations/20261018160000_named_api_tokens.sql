@@ -0,0 +1,16 @@
+-- Add migration script here
+-- Users can have several tokens now, each with a name of its own
+ALTER TABLE "api_tokens" DROP CONSTRAINT IF EXISTS "api_tokens_user_id_key";
+ALTER TABLE "api_tokens" ADD COLUMN IF NOT EXISTS "name" varchar(64) NOT NULL DEFAULT 'API key';
+ALTER TABLE "api_tokens" ADD COLUMN IF NOT EXISTS "token_hash" varchar(64);
+ALTER TABLE "api_tokens" ADD COLUMN IF NOT EXISTS "last_used_at" timestamp with time zone;
+ALTER TABLE "api_tokens" ALTER COLUMN "created_at" TYPE timestamp with time zone;
+
+-- Only a hash of each token is kept. The old tokens came from the login session rather than
+-- standing on their own, so they go and everyone mints named ones instead
+DELETE FROM "api_tokens" WHERE "token_hash" IS NULL;
+ALTER TABLE "api_tokens" ALTER COLUMN "token_hash" SET NOT NULL;
+ALTER TABLE "api_tokens" DROP COLUMN IF EXISTS "token";
+
+CREATE UNIQUE INDEX IF NOT EXISTS api_tokens_token_hash_index ON api_tokens (token_hash);
+CREATE INDEX IF NOT EXISTS api_tokens_user_id_index ON api_tokens (user_id);
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Burnable pastes are gone once read, their tags shouldn't linger in the tag cloud
+DROP MATERIALIZED VIEW IF EXISTS popular_tags;
+CREATE MATERIALIZED VIEW IF NOT EXISTS popular_tags AS
+SELECT tag, COUNT(*) AS frequency
+FROM (
+   SELECT unnest(tags) AS tag
+   FROM pastebin
+   WHERE visibility = 'public' AND NOT burn_after_reading
+) AS flattened_tags
+GROUP BY tag
+ORDER BY frequency DESC, tag;
+
+CREATE UNIQUE INDEX ON popular_tags(tag);
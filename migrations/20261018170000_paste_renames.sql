@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Renamed pastes leave their old paste ID behind, links keep working and nobody else gets to claim it
+CREATE TABLE IF NOT EXISTS "paste_renames" (
+    "old_paste_id" varchar(64) PRIMARY KEY,
+    "paste_id" varchar(64) NOT NULL REFERENCES "pastebin" ("paste_id") ON DELETE CASCADE ON UPDATE CASCADE,
+    "date" timestamp with time zone NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS "paste_renames_paste_id" ON "paste_renames" ("paste_id");
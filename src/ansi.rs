@@ -0,0 +1,279 @@
+use std::fmt::Write;
+
+// Same palette ansi_up uses in the browser so both renderings look alike
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (187, 0, 0),
+    (0, 187, 0),
+    (187, 187, 0),
+    (0, 0, 187),
+    (187, 0, 187),
+    (0, 187, 187),
+    (255, 255, 255),
+    (85, 85, 85),
+    (255, 85, 85),
+    (0, 255, 0),
+    (255, 255, 85),
+    (85, 85, 255),
+    (255, 85, 255),
+    (85, 255, 255),
+    (255, 255, 255),
+];
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Style {
+    fg: Option<(u8, u8, u8)>,
+    bg: Option<(u8, u8, u8)>,
+    bold: bool,
+    underline: bool,
+}
+
+impl Style {
+    fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    fn to_css(self) -> String {
+        let mut css = String::new();
+        if let Some((r, g, b)) = self.fg {
+            let _ = write!(css, "color:#{:02x}{:02x}{:02x};", r, g, b);
+        }
+        if let Some((r, g, b)) = self.bg {
+            let _ = write!(css, "background-color:#{:02x}{:02x}{:02x};", r, g, b);
+        }
+        if self.bold {
+            css.push_str("font-weight:bold;");
+        }
+        if self.underline {
+            css.push_str("text-decoration:underline;");
+        }
+        css
+    }
+
+    fn apply(&mut self, params: &str) {
+        // Both ; and : show up as separators out there
+        let codes: Vec<Option<u32>> = params
+            .split([';', ':'])
+            .map(|code| if code.is_empty() { Some(0) } else { code.parse().ok() })
+            .collect();
+
+        let mut i = 0;
+        while i < codes.len() {
+            match codes[i] {
+                Some(0) => *self = Style::default(),
+                Some(1) => self.bold = true,
+                Some(4) => self.underline = true,
+                Some(22) => self.bold = false,
+                Some(24) => self.underline = false,
+                Some(code @ 30..=37) => self.fg = Some(PALETTE[(code - 30) as usize]),
+                Some(39) => self.fg = None,
+                Some(code @ 40..=47) => self.bg = Some(PALETTE[(code - 40) as usize]),
+                Some(49) => self.bg = None,
+                Some(code @ 90..=97) => self.fg = Some(PALETTE[(code - 90 + 8) as usize]),
+                Some(code @ 100..=107) => self.bg = Some(PALETTE[(code - 100 + 8) as usize]),
+                Some(code @ (38 | 48)) => {
+                    let (colour, used) = extended_colour(&codes[i + 1..]);
+                    if code == 38 {
+                        self.fg = colour;
+                    } else {
+                        self.bg = colour;
+                    }
+                    i += used;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+// Parses what follows a 38 or 48, returning the colour and how many codes it took
+fn extended_colour(codes: &[Option<u32>]) -> (Option<(u8, u8, u8)>, usize) {
+    match codes {
+        [Some(5), Some(n), ..] if *n < 256 => (Some(palette_256(*n as u8)), 2),
+        [Some(2), Some(r), Some(g), Some(b), ..] if *r < 256 && *g < 256 && *b < 256 => {
+            (Some((*r as u8, *g as u8, *b as u8)), 4)
+        }
+        _ => (None, codes.len()),
+    }
+}
+
+fn palette_256(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => PALETTE[n as usize],
+        16..=231 => {
+            let levels = [0, 95, 135, 175, 215, 255];
+            let n = n - 16;
+            (levels[(n / 36) as usize], levels[(n / 6 % 6) as usize], levels[(n % 6) as usize])
+        }
+        _ => {
+            let grey = 8 + (n - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+// Plain text gets the same escaping minus the colours
+pub fn escape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    input.chars().for_each(|c| escape_into(&mut output, c));
+    output
+}
+
+fn escape_into(output: &mut String, c: char) {
+    match c {
+        '&' => output.push_str("&amp;"),
+        '<' => output.push_str("&lt;"),
+        '>' => output.push_str("&gt;"),
+        '"' => output.push_str("&quot;"),
+        '\'' => output.push_str("&#39;"),
+        _ => output.push(c),
+    }
+}
+
+/// Render ANSI SGR escape codes as HTML spans, everything else is escaped or dropped
+pub fn to_html(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut style = Style::default();
+    let mut span_open = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            // Other control characters have no business in HTML
+            if c.is_control() && c != '\n' && c != '\t' {
+                continue;
+            }
+            escape_into(&mut output, c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('[') => {
+                let mut params = String::new();
+                let mut terminator = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        terminator = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+
+                // Only SGR sequences change how things look, cursor movement and the like are dropped
+                if terminator != Some('m') {
+                    continue;
+                }
+
+                let previous = style;
+                style.apply(&params);
+                if style == previous {
+                    continue;
+                }
+
+                if span_open {
+                    output.push_str("</span>");
+                    span_open = false;
+                }
+                if !style.is_plain() {
+                    let _ = write!(output, "<span style=\"{}\">", style.to_css());
+                    span_open = true;
+                }
+            }
+            // OSC sequences end with BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if span_open {
+        output.push_str("</span>");
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, to_html};
+
+    #[test]
+    fn plain_text_passes_through() {
+        assert_eq!(to_html("You say, hello.\n\tIndented"), "You say, hello.\n\tIndented");
+    }
+
+    #[test]
+    fn basic_colours() {
+        assert_eq!(
+            to_html("\x1b[31mred\x1b[0m plain"),
+            r#"<span style="color:#bb0000;">red</span> plain"#
+        );
+        assert_eq!(
+            to_html("\x1b[92;44mbright on blue"),
+            r#"<span style="color:#00ff00;background-color:#0000bb;">bright on blue</span>"#
+        );
+    }
+
+    #[test]
+    fn extended_colours() {
+        assert_eq!(to_html("\x1b[38;5;196mx"), r#"<span style="color:#ff0000;">x</span>"#);
+        assert_eq!(to_html("\x1b[38;5;244mx"), r#"<span style="color:#808080;">x</span>"#);
+        assert_eq!(to_html("\x1b[48;2;1;2;3mx"), r#"<span style="background-color:#010203;">x</span>"#);
+        assert_eq!(to_html("\x1b[38:2:10:20:30mx"), r#"<span style="color:#0a141e;">x</span>"#);
+    }
+
+    #[test]
+    fn bold_and_underline() {
+        assert_eq!(
+            to_html("\x1b[1;4mboth\x1b[22mjust underlined\x1b[24mneither"),
+            concat!(
+                r#"<span style="font-weight:bold;text-decoration:underline;">both</span>"#,
+                r#"<span style="text-decoration:underline;">just underlined</span>neither"#
+            )
+        );
+    }
+
+    #[test]
+    fn resets() {
+        // An empty SGR is a reset as well
+        assert_eq!(to_html("\x1b[33my\x1b[mn"), r#"<span style="color:#bbbb00;">y</span>n"#);
+        assert_eq!(to_html("\x1b[33;41my\x1b[39mn"), concat!(
+            r#"<span style="color:#bbbb00;background-color:#bb0000;">y</span>"#,
+            r#"<span style="background-color:#bb0000;">n</span>"#
+        ));
+        // Resetting what's already plain doesn't leave empty spans behind
+        assert_eq!(to_html("\x1b[0m\x1b[0mtext"), "text");
+    }
+
+    #[test]
+    fn spans_close_at_the_end() {
+        assert_eq!(to_html("\x1b[34mblue"), r#"<span style="color:#0000bb;">blue</span>"#);
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            to_html("\x1b[31m<script>alert(\"x\" & 'y')</script>"),
+            r#"<span style="color:#bb0000;">&lt;script&gt;alert(&quot;x&quot; &amp; &#39;y&#39;)&lt;/script&gt;</span>"#
+        );
+        assert_eq!(escape("<b>&</b>"), "&lt;b&gt;&amp;&lt;/b&gt;");
+    }
+
+    #[test]
+    fn drops_other_sequences_and_controls() {
+        assert_eq!(to_html("a\x1b[2Kb\x1b[1;1Hc"), "abc");
+        assert_eq!(to_html("\x1b]0;window title\x07text"), "text");
+        assert_eq!(to_html("\x1b]8;;https://example.com\x1b\\link"), "link");
+        assert_eq!(to_html("bell\x07 and\r backspace\x08"), "bell and backspace");
+    }
+}
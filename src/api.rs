@@ -3,16 +3,22 @@ use crate::forms;
 use crate::paste;
 use crate::runtime;
 use crate::templates;
+use crate::uploads;
 use crate::utils;
-use axum::extract::{Json as JsonForm, Path, State};
+use axum::body::Bytes;
+use axum::extract::{Form, Json as JsonForm, Path, Query, State};
 use axum_extra::{TypedHeader, headers::Host};
+use axum::http::header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE};
 use axum::http::{HeaderMap, StatusCode};
-use axum::response::{IntoResponse, Json, Response};
-use chrono::Utc;
+use axum::response::{IntoResponse, Json, Redirect, Response};
+use axum_csrf::CsrfToken;
+use chrono::{DateTime, Utc};
+use rand::RngExt;
 use scc::HashMap;
 use serde::Serialize;
-use sqlx::Error::RowNotFound;
-use sqlx::{query, query_scalar};
+use sha2::{Digest, Sha256};
+use sqlx::{query, query_as, query_scalar, FromRow};
+use std::collections::HashMap as StdHashMap;
 use std::sync::Arc;
 use std::sync::OnceLock;
 use tokio::time::{sleep, Duration};
@@ -20,6 +26,9 @@ use tower_cookies::Cookies;
 use tracing::{error, warn};
 
 const DAILY_LIMIT: u8 = 50; // we allow 50 requests per user per day
+const PAGE_SIZE: i64 = 50; // pastes per page when listing a user's pastes
+const MAX_PAGE: i64 = 10_000; // keeps the offset well clear of overflowing
+const MAX_API_TOKENS: i64 = 10; // one for each bot or script should be plenty
 
 struct RateLimit {
     daily_count: u8,
@@ -65,7 +74,59 @@ struct APISuccess {
     url: String,
 }
 
-async fn identify_user(
+#[derive(Serialize)]
+struct APIPaste {
+    paste_id: String,
+    title: Option<String>,
+    tags: Vec<String>,
+    format: paste::PasteFormat,
+    date: DateTime<Utc>,
+    views: i64,
+    visibility: paste::PasteVisibility,
+    url: String,
+    content_url: String,
+}
+
+impl APIPaste {
+    fn new(hostname: &Host, paste: paste::UserPaste, views: i64) -> Self {
+        APIPaste {
+            url: format!("https://{}/pastebin/{}", hostname, &paste.paste_id),
+            content_url: format!("https://{}/pastebin/api/v1/{}/content", hostname, &paste.paste_id),
+            paste_id: paste.paste_id,
+            title: paste.title,
+            tags: paste.tags.unwrap_or_default(),
+            format: paste.format,
+            date: paste.date,
+            views,
+            visibility: paste.visibility,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct APIPasteInfo {
+    success: bool,
+    #[serde(flatten)]
+    paste: APIPaste,
+}
+
+#[derive(Serialize)]
+struct APIPasteList {
+    success: bool,
+    page: i64,
+    more: bool,
+    pastes: Vec<APIPaste>,
+}
+
+#[derive(Serialize)]
+struct UploadStatus {
+    success: bool,
+    upload_id: String,
+    offset: usize,
+}
+
+// Who's behind the API token, without counting it against their daily limit
+async fn authenticate(
     state: &Arc<runtime::AppState>,
     headers: HeaderMap,
 ) -> Result<(String, String), PastebinError> {
@@ -90,13 +151,44 @@ async fn identify_user(
         }
     };
 
-    let cookies = Cookies::default();
-    cookies.add(utils::build_auth_cookie(state, token.to_string()));
-    let (user_id, session_id) = utils::get_user_id(state, &cookies);
-    let (user_id, session_id) = match (user_id, session_id) {
-        (Some(uid), Some(sid)) => (uid, sid),
-        _ => return Err(PastebinError::Auth("Invalid API token!".to_string())),
-    };
+    // Revoked tokens are gone from the table, so they aren't found either
+    let found = query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = NOW()
+        WHERE token_hash = $1
+        RETURNING id, user_id
+        "#,
+        hash_token(&token)
+    )
+    .fetch_optional(&state.db)
+    .await?;
+
+    match found {
+        // Pastes made through the API remember which token made them
+        Some(found) => Ok((found.user_id, format!("api-{}", found.id))),
+        None => {
+            warn!("Unknown API token used: {}..", &hash_token(&token)[..12]);
+            Err(PastebinError::Auth("Invalid API token! Please generate a new one".to_string()))
+        }
+    }
+}
+
+fn generate_token() -> String {
+    let secret: [u8; 32] = rand::rng().random();
+    hex::encode(secret)
+}
+
+// Only hashes of tokens are kept, the token itself is shown once when it's made
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+async fn identify_user(
+    state: &Arc<runtime::AppState>,
+    headers: HeaderMap,
+) -> Result<(String, String), PastebinError> {
+    let (user_id, session_id) = authenticate(state, headers).await?;
 
     // Check if the user is rate limited
     if rate_limited(state, &user_id).await {
@@ -105,47 +197,62 @@ async fn identify_user(
         ));
     }
 
-    // Check if user's token has not been revoked
-    let token_present: bool = match query_scalar!(
-        r#"SELECT EXISTS(SELECT 1 FROM api_tokens WHERE user_id=$1 AND token=$2)"#,
-        &user_id,
-        &token,
-    ).fetch_one(&state.db).await {
-        Ok(present) => present.unwrap_or(false),
-        Err(err) => match err {
-            RowNotFound => false,
-            _ => {
-                return Err(PastebinError::Database(err));
-            }
-        }
-    };
+    Ok((user_id, session_id))
+}
 
-    if !token_present {
-        warn!("Token not found for user: {} | {}", user_id, token);
-        return Err(PastebinError::Auth("Invalid API token! Please generate a new one".to_string()));
+// The API takes the same options as the paste form and always saves to our datastore
+fn paste_form(
+    payload: forms::PasteAPIForm,
+    content: String,
+    format: String,
+    language: Option<String>,
+) -> forms::PasteForm {
+    forms::PasteForm {
+        content,
+        title: payload.title,
+        tags: payload.tags,
+        format,
+        destination: forms::ValidDestination::DataStore,
+        expiry: payload.expiry,
+        burn_after_reading: payload.burn_after_reading,
+        visibility: payload.visibility,
+        password: payload.password,
+        slug: payload.slug,
+        language,
+        password_hash: None,
+        forked_from: None,
+        files: payload.files,
+        csrf_token: "".to_string(),
+        token: "".to_string(),
     }
-
-    Ok((user_id, session_id))
 }
 
 pub async fn create(
     State(state): State<Arc<runtime::AppState>>,
     headers: HeaderMap,
     TypedHeader(hostname): TypedHeader<Host>,
-    JsonForm(payload): JsonForm<forms::PasteAPIForm>,
+    JsonForm(mut payload): JsonForm<forms::PasteAPIForm>,
 ) -> Result<Response, PastebinError> {
     let (user_id, session_id) = identify_user(&state, headers).await?;
 
-    let payload = forms::PasteForm {
-        content: payload.content,
-        title: payload.title,
-        tags: payload.tags,
-        format: payload.format,
-        destination: forms::ValidDestination::DataStore,
-        csrf_token: "".to_string(),
-        token: "".to_string(),
+    if !payload.files.is_empty() && !payload.content.is_empty() {
+        return Err(PastebinError::Validation(
+            "Send either content or files, not both!".to_string(),
+        ));
+    }
+
+    // Bundles take their first file as the paste's own content
+    let (content, format, language) = match payload.files.first() {
+        Some(file) => (file.content.clone(), file.format.clone(), file.language.clone()),
+        None => (
+            std::mem::take(&mut payload.content),
+            std::mem::take(&mut payload.format),
+            payload.language.take(),
+        ),
     };
 
+    let payload = paste_form(payload, content, format, language);
+
     // Create the paste, use the special score 0.5 for API pastes
     let paste_id = paste::new_paste(
         &state,
@@ -193,6 +300,250 @@ pub async fn delete(
     ).into_response())
 }
 
+// Pastes the caller can't see are as good as missing, locked ones are only readable by their owner
+async fn readable_paste(
+    state: &Arc<runtime::AppState>,
+    paste_id: &str,
+    user_id: &str,
+) -> Result<paste::Paste, PastebinError> {
+    let paste = paste::Paste::get(&state.db, paste_id).await?;
+    let user_id = Some(user_id.to_string());
+
+    if !paste.can_view(&user_id) {
+        return Err(PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    if paste.password_hash.is_some() && paste.user_id != user_id {
+        return Err(PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    Ok(paste)
+}
+
+pub async fn get(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    TypedHeader(hostname): TypedHeader<Host>,
+    Path(paste_id): Path<String>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = identify_user(&state, headers).await?;
+
+    let paste = readable_paste(&state, &paste_id, &user_id).await?;
+
+    Ok(Json(paste_info(&hostname, paste).await).into_response())
+}
+
+async fn paste_info(hostname: &Host, paste: paste::Paste) -> APIPasteInfo {
+    let views = paste::Paste::peek_views(&paste.paste_id, paste.views).await;
+
+    let paste = paste::UserPaste {
+        paste_id: paste.paste_id,
+        title: paste.title,
+        tags: paste.tags,
+        format: paste.format,
+        date: paste.date,
+        views: paste.views,
+        visibility: paste.visibility,
+    };
+
+    APIPasteInfo {
+        success: true,
+        paste: APIPaste::new(hostname, paste, views),
+    }
+}
+
+pub async fn update(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    TypedHeader(hostname): TypedHeader<Host>,
+    Path(paste_id): Path<String>,
+    JsonForm(payload): JsonForm<forms::PasteAPIEditForm>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = identify_user(&state, headers).await?;
+
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    if Some(&user_id) != paste.user_id.as_ref() {
+        return Err(PastebinError::Auth("You don't own this paste!".to_string()));
+    }
+
+    // Editing always sets the title and tags, so the ones left out keep what they were
+    let title = payload.title.or_else(|| paste.title.clone());
+    let tags = payload.tags.or_else(|| paste.tags.as_ref().map(|tags| tags.join(" ")));
+
+    let paste_id = paste.edit(&state, &title, &tags, &payload.content, &None).await?;
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    Ok(Json(paste_info(&hostname, paste).await).into_response())
+}
+
+pub async fn content(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    Path(paste_id): Path<String>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = identify_user(&state, headers).await?;
+
+    let paste = readable_paste(&state, &paste_id, &user_id).await?;
+
+    // Burnable pastes go the same way they do on the paste page
+    let content = if paste.burn_after_reading && paste.user_id.as_ref() != Some(&user_id) {
+        paste.burn(&state).await?
+    } else {
+        paste.get_content(&state).await?
+    };
+
+    let mut response_headers = HeaderMap::new();
+    let content_type = paste.get_content_type();
+    let content_type = if content_type.starts_with("text/") {
+        format!("{}; charset=utf-8", content_type)
+    } else {
+        content_type
+    };
+    if let Ok(content_type) = content_type.parse() {
+        response_headers.insert(CONTENT_TYPE, content_type);
+    }
+    response_headers.insert(CONTENT_SECURITY_POLICY, "sandbox".parse().unwrap());
+    response_headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+
+    Ok((StatusCode::OK, response_headers, content).into_response())
+}
+
+pub async fn list(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    TypedHeader(hostname): TypedHeader<Host>,
+    Query(params): Query<StdHashMap<String, String>>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = identify_user(&state, headers).await?;
+
+    let page: i64 = params
+        .get("page")
+        .map(|s| s.parse().unwrap_or(1))
+        .unwrap_or(1)
+        .clamp(1, MAX_PAGE);
+
+    let (pastes, more) = paste::Paste::list_for_user(&state.db, &user_id, page, PAGE_SIZE).await?;
+
+    let mut listed = Vec::with_capacity(pastes.len());
+    for paste in pastes {
+        let views = paste::Paste::peek_views(&paste.paste_id, paste.views).await;
+        listed.push(APIPaste::new(&hostname, paste, views));
+    }
+
+    Ok(Json(APIPasteList {
+        success: true,
+        page,
+        more,
+        pastes: listed,
+    }).into_response())
+}
+
+// Big logs go up a chunk at a time, the paste is only made once the upload is finished
+pub async fn start_upload(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    JsonForm(payload): JsonForm<forms::PasteAPIForm>,
+) -> Result<Response, PastebinError> {
+    let (user_id, session_id) = identify_user(&state, headers).await?;
+
+    if !payload.content.is_empty() || !payload.files.is_empty() {
+        return Err(PastebinError::Validation(
+            "Send the content in chunks once the upload has started!".to_string(),
+        ));
+    }
+
+    let format = payload.format.clone();
+    let language = payload.language.clone();
+    let form = paste_form(payload, String::new(), format, language);
+    let paste = paste::Paste::new_upload(&state, &form, Some(user_id.clone()), Some(session_id)).await?;
+    let upload_id = uploads::start(&state, paste, user_id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadStatus {
+            success: true,
+            upload_id,
+            offset: 0,
+        }),
+    ).into_response())
+}
+
+pub async fn upload_status(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = authenticate(&state, headers).await?;
+
+    let offset = uploads::status(&upload_id, &user_id).await?;
+
+    Ok(Json(UploadStatus {
+        success: true,
+        upload_id,
+        offset,
+    }).into_response())
+}
+
+pub async fn upload_chunk(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+    Query(params): Query<StdHashMap<String, String>>,
+    body: Bytes,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = authenticate(&state, headers).await?;
+
+    let offset = match params.get("offset").map(|offset| offset.parse::<usize>()) {
+        Some(Ok(offset)) => offset,
+        _ => {
+            return Err(PastebinError::Validation(
+                "Every chunk needs its ?offset=<n>!".to_string(),
+            ))
+        }
+    };
+
+    let offset = uploads::append(&state, &upload_id, &user_id, offset, body).await?;
+
+    Ok(Json(UploadStatus {
+        success: true,
+        upload_id,
+        offset,
+    }).into_response())
+}
+
+pub async fn finish_upload(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    TypedHeader(hostname): TypedHeader<Host>,
+    Path(upload_id): Path<String>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = authenticate(&state, headers).await?;
+
+    let paste_id = uploads::finish(&state, &upload_id, &user_id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(APISuccess {
+            success: true,
+            url: format!("https://{}/pastebin/{}", hostname, &paste_id),
+            paste_id,
+        }),
+    ).into_response())
+}
+
+pub async fn abort_upload(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    Path(upload_id): Path<String>,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = authenticate(&state, headers).await?;
+
+    uploads::abort(&state, &upload_id, &user_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()).into_response())
+}
+
 pub async fn reset_api() {
     loop {
         let now = Utc::now();
@@ -210,52 +561,163 @@ pub async fn reset_api() {
 pub async fn about(
     State(state): State<Arc<runtime::AppState>>,
     cookies: Cookies,
-) -> Result<Response, PastebinError> {
+) -> impl IntoResponse {
     let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let template = templates::APIAboutTemplate {
+        static_domain: state.config.static_domain.clone(),
+        user_id,
+        upload_max_size: state.config.upload_max_size,
+    };
 
-    if user_id.is_none() {
-        let template = templates::APIAboutTemplate {
-            static_domain: state.config.static_domain.clone(),
-            user_id,
-            api_key: "".to_string(),
-        };
-        return Ok(templates::HtmlTemplate(template).into_response());
-    }
+    templates::HtmlTemplate(template)
+}
 
-    let user_id = user_id.unwrap();
+#[derive(FromRow)]
+pub struct APIToken {
+    pub id: i32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
 
-    let api_key: String = match query_scalar!(
-        r#"SELECT token FROM api_tokens WHERE user_id=$1 LIMIT 1"#,
+async fn render_tokens(
+    state: &Arc<runtime::AppState>,
+    token: CsrfToken,
+    user_id: String,
+    new_token: Option<String>,
+) -> Result<Response, PastebinError> {
+    let tokens = query_as!(
+        APIToken,
+        r#"
+        SELECT id, name, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
         &user_id
-    ).fetch_one(&state.db).await {
-        Ok(token) => token,
-        Err(err) => match err {
-            RowNotFound => {
-                let token = cookies.get(utils::get_cookie_name(&state, "_app_session")
-                    .as_str()).map(|c| c.value().to_string()).unwrap_or_default();
-
-                query!(
-                    r#"
-                    INSERT INTO api_tokens (user_id, token)
-                    VALUES ($1, $2)
-                    ON CONFLICT (user_id) DO UPDATE
-                    SET token=$2
-                    "#,
-                    &user_id,
-                    &token,
-                ).execute(&state.db).await?;
-                token
-            }
-            _ => {
-                return Err(PastebinError::Database(err));
-            }
-        },
-    };
+    )
+    .fetch_all(&state.db)
+    .await?;
 
-    let template = templates::APIAboutTemplate {
+    let template = templates::APITokensTemplate {
         static_domain: state.config.static_domain.clone(),
+        csrf_token: token.authenticity_token().unwrap(),
         user_id: Some(user_id),
-        api_key,
+        tokens,
+        new_token,
+        max_tokens: MAX_API_TOKENS,
+    };
+
+    Ok((token, templates::HtmlTemplate(template)).into_response())
+}
+
+pub async fn tokens(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    token: CsrfToken,
+) -> Result<Response, PastebinError> {
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Ok(Redirect::to("/pastebin/auth/discord/start").into_response()),
+    };
+
+    render_tokens(&state, token, user_id, None).await
+}
+
+pub async fn create_token(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    token: CsrfToken,
+    Form(payload): Form<forms::APITokenForm>,
+) -> Result<Response, PastebinError> {
+    // Verify the CSRF token
+    if token.verify(&payload.csrf_token).is_err() {
+        return Err(PastebinError::Auth("CSRF token is not valid!".to_string()));
+    }
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Err(PastebinError::Auth("Sign in to make API tokens!".to_string())),
     };
-    Ok(templates::HtmlTemplate(template).into_response())
+
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(PastebinError::Validation("Give your token a name!".to_string()));
+    }
+    if name.chars().count() > 64 {
+        return Err(PastebinError::Validation("Token names can be up to 64 characters!".to_string()));
+    }
+
+    let mut transaction = state.db.begin().await?;
+
+    // Concurrent requests take turns, so they can't each slip in under the limit
+    query!("SELECT pg_advisory_xact_lock(hashtext('api_tokens:' || $1))", &user_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    let count = query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM api_tokens WHERE user_id = $1"#,
+        &user_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if count >= MAX_API_TOKENS {
+        return Err(PastebinError::TooMany(format!(
+            "You can have up to {} API tokens, revoke one you don't need first!",
+            MAX_API_TOKENS
+        )));
+    }
+
+    let api_token = generate_token();
+    query!(
+        r#"
+        INSERT INTO api_tokens (user_id, name, token_hash, created_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        &user_id,
+        name,
+        hash_token(&api_token),
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    render_tokens(&state, token, user_id, Some(api_token)).await
+}
+
+pub async fn revoke_token(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    token: CsrfToken,
+    Path(token_id): Path<i32>,
+    Form(payload): Form<forms::APITokenRevokeForm>,
+) -> Result<Response, PastebinError> {
+    // Verify the CSRF token
+    if token.verify(&payload.csrf_token).is_err() {
+        return Err(PastebinError::Auth("CSRF token is not valid!".to_string()));
+    }
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Err(PastebinError::Auth("Sign in to revoke API tokens!".to_string())),
+    };
+
+    let revoked = query!(
+        r#"DELETE FROM api_tokens WHERE id = $1 AND user_id = $2"#,
+        token_id,
+        &user_id
+    )
+    .execute(&state.db)
+    .await?;
+
+    if revoked.rows_affected() == 0 {
+        return Err(PastebinError::NotFound("Token not found".to_string()));
+    }
+
+    Ok(Redirect::to("/pastebin/account/tokens").into_response())
 }
@@ -1,6 +1,7 @@
 use crate::runtime;
 use scc::HashSet;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::OnceLock;
 use tokio::time::{sleep, Duration};
 use tracing::{error, info};
@@ -10,6 +11,46 @@ pub fn queue() -> &'static HashSet<String> {
     PURGE_QUEUE.get_or_init(|| HashSet::with_capacity(20))
 }
 
+// Cloudflare's edge ranges, as listed at https://www.cloudflare.com/ips/
+const EDGE_V4: [(Ipv4Addr, u32); 15] = [
+    (Ipv4Addr::new(173, 245, 48, 0), 20),
+    (Ipv4Addr::new(103, 21, 244, 0), 22),
+    (Ipv4Addr::new(103, 22, 200, 0), 22),
+    (Ipv4Addr::new(103, 31, 4, 0), 22),
+    (Ipv4Addr::new(141, 101, 64, 0), 18),
+    (Ipv4Addr::new(108, 162, 192, 0), 18),
+    (Ipv4Addr::new(190, 93, 240, 0), 20),
+    (Ipv4Addr::new(188, 114, 96, 0), 20),
+    (Ipv4Addr::new(197, 234, 240, 0), 22),
+    (Ipv4Addr::new(198, 41, 128, 0), 17),
+    (Ipv4Addr::new(162, 158, 0, 0), 15),
+    (Ipv4Addr::new(104, 16, 0, 0), 13),
+    (Ipv4Addr::new(104, 24, 0, 0), 14),
+    (Ipv4Addr::new(172, 64, 0, 0), 13),
+    (Ipv4Addr::new(131, 0, 72, 0), 22),
+];
+const EDGE_V6: [(Ipv6Addr, u32); 7] = [
+    (Ipv6Addr::new(0x2400, 0xcb00, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2606, 0x4700, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2803, 0xf800, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2405, 0xb500, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2405, 0x8100, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2a06, 0x98c0, 0, 0, 0, 0, 0, 0), 29),
+    (Ipv6Addr::new(0x2c0f, 0xf248, 0, 0, 0, 0, 0, 0), 32),
+];
+
+/// Whether a request came to us straight from one of Cloudflare's edges
+pub fn is_edge(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => EDGE_V4
+            .iter()
+            .any(|(net, len)| u32::from(ip) >> (32 - len) == u32::from(*net) >> (32 - len)),
+        IpAddr::V6(ip) => EDGE_V6
+            .iter()
+            .any(|(net, len)| u128::from(ip) >> (128 - len) == u128::from(*net) >> (128 - len)),
+    }
+}
+
 static CF_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 
 fn get_client() -> &'static reqwest::Client {
@@ -31,7 +72,12 @@ pub async fn purge_cache(state: &runtime::AppState, now: bool) {
 
         let mut urls: Vec<String> = Vec::new();
         queue().iter_async(|key| {
-            urls.push(format!("{}{}", state.config.s3_bucket_url, key.clone()));
+            // Objects are queued by their S3 key, pages on our own domains by their full URL
+            if key.starts_with("https://") {
+                urls.push(key.clone());
+            } else {
+                urls.push(format!("{}{}", state.config.s3_bucket_url, key.clone()));
+            }
             true
         }).await;
 
@@ -0,0 +1,223 @@
+use brotli::{CompressorWriter, Decompressor};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgTypeInfo, PgValueRef, Postgres};
+use std::io::{Read, Result, Write};
+
+/// How paste content is encoded on its way into storage
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum Encoding {
+    #[serde(rename = "identity")]
+    Identity,
+    #[serde(rename = "br")]
+    Brotli,
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    // Doubles as the Content-Encoding header value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    // Tacked onto S3 keys so whoever browses the bucket knows what they're looking at
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Encoding::Identity => "",
+            Encoding::Brotli => ".br",
+            Encoding::Gzip => ".gz",
+            Encoding::Zstd => ".zst",
+        }
+    }
+}
+
+impl TryFrom<String> for Encoding {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.as_str() {
+            "identity" => Ok(Encoding::Identity),
+            "br" => Ok(Encoding::Brotli),
+            "gzip" => Ok(Encoding::Gzip),
+            "zstd" => Ok(Encoding::Zstd),
+            _ => Err(format!("Unknown content encoding: {}", value)),
+        }
+    }
+}
+
+impl sqlx::Type<Postgres> for Encoding {
+    fn type_info() -> PgTypeInfo {
+        <String as sqlx::Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as sqlx::Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for Encoding {
+    fn decode(value: PgValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        let encoding = <String as sqlx::Decode<Postgres>>::decode(value)?;
+        Ok(Encoding::try_from(encoding)?)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CodecSettings {
+    pub brotli_quality: u32,
+    pub brotli_window: u32,
+    pub zstd_level: i32,
+}
+
+pub fn compress(content: &[u8], encoding: Encoding, settings: CodecSettings) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(content.to_vec()),
+        Encoding::Brotli => {
+            let mut output = Vec::new();
+            let mut encoder =
+                CompressorWriter::new(&mut output, 4096, settings.brotli_quality, settings.brotli_window);
+            encoder.write_all(content)?;
+            encoder.flush()?;
+            drop(encoder);
+            Ok(output)
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content)?;
+            encoder.finish()
+        }
+        Encoding::Zstd => zstd::encode_all(content, settings.zstd_level),
+    }
+}
+
+pub fn decompress(content: &[u8], encoding: Encoding) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(content.to_vec()),
+        Encoding::Brotli => {
+            let mut output = Vec::new();
+            Decompressor::new(content, 4096).read_to_end(&mut output)?;
+            Ok(output)
+        }
+        Encoding::Gzip => {
+            let mut output = Vec::new();
+            GzDecoder::new(content).read_to_end(&mut output)?;
+            Ok(output)
+        }
+        Encoding::Zstd => zstd::decode_all(content),
+    }
+}
+
+/// Encodes content handed over a piece at a time, for uploads too big to hold at once
+pub enum StreamEncoder {
+    Identity(Vec<u8>),
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(zstd::Encoder<'static, Vec<u8>>),
+}
+
+impl StreamEncoder {
+    pub fn new(encoding: Encoding, settings: CodecSettings) -> Result<Self> {
+        match encoding {
+            Encoding::Identity => Ok(StreamEncoder::Identity(Vec::new())),
+            Encoding::Brotli => Ok(StreamEncoder::Brotli(Box::new(CompressorWriter::new(
+                Vec::new(),
+                4096,
+                settings.brotli_quality,
+                settings.brotli_window,
+            )))),
+            Encoding::Gzip => Ok(StreamEncoder::Gzip(GzEncoder::new(Vec::new(), Compression::default()))),
+            Encoding::Zstd => Ok(StreamEncoder::Zstd(zstd::Encoder::new(Vec::new(), settings.zstd_level)?)),
+        }
+    }
+
+    pub fn write(&mut self, content: &[u8]) -> Result<()> {
+        match self {
+            StreamEncoder::Identity(output) => output.extend_from_slice(content),
+            StreamEncoder::Brotli(encoder) => encoder.write_all(content)?,
+            StreamEncoder::Gzip(encoder) => encoder.write_all(content)?,
+            StreamEncoder::Zstd(encoder) => encoder.write_all(content)?,
+        }
+        Ok(())
+    }
+
+    // Hands over whatever has been encoded so far
+    pub fn take(&mut self) -> Vec<u8> {
+        match self {
+            StreamEncoder::Identity(output) => std::mem::take(output),
+            StreamEncoder::Brotli(encoder) => std::mem::take(encoder.get_mut()),
+            StreamEncoder::Gzip(encoder) => std::mem::take(encoder.get_mut()),
+            StreamEncoder::Zstd(encoder) => std::mem::take(encoder.get_mut()),
+        }
+    }
+
+    // The rest of the encoded content, end of stream and all
+    pub fn finish(self) -> Result<Vec<u8>> {
+        match self {
+            StreamEncoder::Identity(output) => Ok(output),
+            StreamEncoder::Brotli(encoder) => Ok(encoder.into_inner()),
+            StreamEncoder::Gzip(encoder) => encoder.finish(),
+            StreamEncoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, CodecSettings, Encoding, StreamEncoder};
+
+    const ENCODINGS: [Encoding; 4] = [Encoding::Identity, Encoding::Brotli, Encoding::Gzip, Encoding::Zstd];
+
+    fn settings() -> CodecSettings {
+        CodecSettings {
+            brotli_quality: 6,
+            brotli_window: 22,
+            zstd_level: 3,
+        }
+    }
+
+    fn content() -> Vec<u8> {
+        "[12:00:01] You say, \"Hello there!\"\n".repeat(2000).into_bytes()
+    }
+
+    #[test]
+    fn round_trips() {
+        let content = content();
+        for encoding in ENCODINGS {
+            let compressed = compress(&content, encoding, settings()).unwrap();
+            assert_eq!(decompress(&compressed, encoding).unwrap(), content, "{}", encoding.as_str());
+        }
+    }
+
+    #[test]
+    fn stream_round_trips() {
+        let content = content();
+        for encoding in ENCODINGS {
+            let mut encoder = StreamEncoder::new(encoding, settings()).unwrap();
+            let mut compressed = Vec::new();
+            for chunk in content.chunks(4096) {
+                encoder.write(chunk).unwrap();
+                compressed.extend(encoder.take());
+            }
+            compressed.extend(encoder.finish().unwrap());
+            assert_eq!(decompress(&compressed, encoding).unwrap(), content, "{}", encoding.as_str());
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for encoding in [Encoding::Brotli, Encoding::Gzip, Encoding::Zstd] {
+            assert!(decompress(b"not compressed at all", encoding).is_err(), "{}", encoding.as_str());
+        }
+    }
+}
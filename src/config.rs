@@ -1,5 +1,8 @@
 use config::{Config, Environment};
 use serde::Deserialize;
+use crate::compression::{CodecSettings, Encoding};
+use crate::paste::{FORMATS, MAX_PASTE_ID_LENGTH};
+use std::collections::HashMap;
 use tracing::{error, info};
 
 #[derive(Deserialize)]
@@ -13,6 +16,56 @@ pub struct OauthConfig {
     pub scopes: String,
 }
 
+#[derive(Deserialize)]
+pub struct CompressionConfig {
+    pub encoding: Encoding,
+    pub min_size: usize,
+    pub brotli_quality: u32,
+    pub brotli_window: u32,
+    pub zstd_level: i32,
+
+    // Per format overrides of the encoding above, keyed like the API's format field
+    #[serde(default)]
+    pub formats: HashMap<String, Encoding>,
+}
+
+impl CompressionConfig {
+    pub fn settings(&self) -> CodecSettings {
+        CodecSettings {
+            brotli_quality: self.brotli_quality,
+            brotli_window: self.brotli_window,
+            zstd_level: self.zstd_level,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct QuotaTier {
+    // Leave either one out for no limit
+    pub max_bytes: Option<i64>,
+    pub max_pastes: Option<i64>,
+
+    // Signed in users on this tier, by the user_id we keep for them
+    #[serde(default)]
+    pub users: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct QuotaConfig {
+    pub default_tier: String,
+    pub tiers: HashMap<String, QuotaTier>,
+}
+
+impl QuotaConfig {
+    pub fn tier(&self, user_id: &str) -> (&str, &QuotaTier) {
+        self.tiers
+            .iter()
+            .find(|(_, tier)| tier.users.iter().any(|user| user == user_id))
+            .map(|(name, tier)| (name.as_str(), tier))
+            .unwrap_or((&self.default_tier, &self.tiers[&self.default_tier])) // Checked at startup
+    }
+}
+
 #[derive(Deserialize)]
 pub struct AppConfig {
     pub allowed_domains: Vec<String>,
@@ -32,16 +85,25 @@ pub struct AppConfig {
     pub cookie_salt: String,
     pub cookie_secure: bool,
     pub update_views_interval: u64,
+    pub reap_expired_interval: u64,
+    pub upload_max_size: usize,
+
+    pub paste_id_length: usize,
+    pub paste_id_alphabet: String,
 
     pub s3_bucket_url: String,
     pub s3_bucket: String,
     pub s3_prefix: String,
+    pub s3_private_prefix: String,
 
     pub aws_region: String,
     pub aws_access_key_id: String,
     pub aws_secret_access_key: String,
     pub aws_endpoint: String,
 
+    pub compression: CompressionConfig,
+    pub quotas: QuotaConfig,
+
     pub discord_oauth: OauthConfig,
     pub drive_oauth: OauthConfig,
 }
@@ -72,6 +134,16 @@ impl AppConfig {
         // config = config.set_default("cookie_salt", "-bork-bork-").unwrap();
         config = config.set_default("cookie_secure", true).unwrap();
         config = config.set_default("update_views_interval", 300).unwrap();
+        config = config.set_default("reap_expired_interval", 600).unwrap();
+        config = config.set_default("upload_max_size", 64 * 1024 * 1024).unwrap();
+
+        config = config.set_default("paste_id_length", 8).unwrap();
+        config = config
+            .set_default(
+                "paste_id_alphabet",
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_.~",
+            )
+            .unwrap();
 
         config = config
             .set_default("s3_bucket_url", "https://bin.ada-young.com/")
@@ -80,6 +152,7 @@ impl AppConfig {
             .set_default("s3_bucket", "bin.ada-young.com")
             .unwrap();
         config = config.set_default("s3_prefix", "content/").unwrap();
+        config = config.set_default("s3_private_prefix", "private/").unwrap();
 
         config = config.set_default("aws_region", "us-east-1").unwrap();
         config = config.set_default("aws_access_key_id", "").unwrap();
@@ -88,6 +161,18 @@ impl AppConfig {
             .set_default("aws_endpoint", "s3.amazonaws.com")
             .unwrap();
 
+        config = config.set_default("compression.encoding", "br").unwrap();
+        config = config.set_default("compression.min_size", 1024).unwrap();
+        config = config.set_default("compression.brotli_quality", 6).unwrap();
+        config = config.set_default("compression.brotli_window", 22).unwrap();
+        config = config.set_default("compression.zstd_level", 3).unwrap();
+
+        config = config.set_default("quotas.default_tier", "default").unwrap();
+        config = config
+            .set_default("quotas.tiers.default.max_bytes", 256 * 1024 * 1024)
+            .unwrap();
+        config = config.set_default("quotas.tiers.default.max_pastes", 5000).unwrap();
+
         // Check for the presence of a config.toml file and use it
         if std::path::Path::new("config.toml").is_file() {
             info!("Found config.toml, using it!");
@@ -109,12 +194,71 @@ impl AppConfig {
         };
 
         // Deserialize the config
-        match config.try_deserialize() {
+        let config: AppConfig = match config.try_deserialize() {
             Ok(config) => config,
             Err(err) => {
                 error!("Error deserializing config: {}", err);
                 std::process::exit(1);
             }
+        };
+
+        // Paste IDs have to fit in the database and stay URL safe
+        if !(4..=MAX_PASTE_ID_LENGTH).contains(&config.paste_id_length) {
+            error!("paste_id_length must be between 4 and {}!", MAX_PASTE_ID_LENGTH);
+            std::process::exit(1);
+        }
+        if !config
+            .paste_id_alphabet
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.~".contains(c))
+        {
+            error!("paste_id_alphabet may only contain A-Z, a-z, 0-9 and -_.~ characters!");
+            std::process::exit(1);
+        }
+        if !config.paste_id_alphabet.chars().any(|c| c.is_ascii_alphanumeric()) {
+            error!("paste_id_alphabet needs at least one alphanumeric character!");
+            std::process::exit(1);
+        }
+
+        // Upload sizes end up in an integer column
+        if !(1024 * 1024..=1024 * 1024 * 1024).contains(&config.upload_max_size) {
+            error!("upload_max_size must be between 1MB and 1GB!");
+            std::process::exit(1);
+        }
+
+        // Catch compression settings we can't honour now rather than on the first paste
+        let compression = &config.compression;
+        if compression.brotli_quality > 11 {
+            error!("compression.brotli_quality must be between 0 and 11!");
+            std::process::exit(1);
         }
+        if !(10..=24).contains(&compression.brotli_window) {
+            error!("compression.brotli_window must be between 10 and 24!");
+            std::process::exit(1);
+        }
+        for key in compression.formats.keys() {
+            if !FORMATS.iter().any(|spec| spec.key == key) {
+                error!("compression.formats has an unknown paste format: {}", key);
+                std::process::exit(1);
+            }
+        }
+        if !(1..=22).contains(&compression.zstd_level) {
+            error!("compression.zstd_level must be between 1 and 22!");
+            std::process::exit(1);
+        }
+
+        let quotas = &config.quotas;
+        if !quotas.tiers.contains_key(&quotas.default_tier) {
+            error!("quotas.default_tier {} isn't one of the quotas.tiers!", quotas.default_tier);
+            std::process::exit(1);
+        }
+        for (name, tier) in quotas.tiers.iter() {
+            if tier.max_bytes.is_some_and(|max| max < 1) || tier.max_pastes.is_some_and(|max| max < 1) {
+                error!("quotas.tiers.{} limits must be above zero, leave them out for no limit!", name);
+                std::process::exit(1);
+            }
+        }
+
+        config
     }
 }
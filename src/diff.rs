@@ -0,0 +1,310 @@
+use crate::errors::PastebinError;
+
+// Anything further apart than this isn't worth diffing line by line
+const MAX_EDITS: usize = 2000;
+// Nor is anything this big, the whole of it ends up in the page
+const MAX_BYTES: usize = 1024 * 1024;
+const MAX_LINES: usize = 20_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+impl DiffOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffOp::Equal => "equal",
+            DiffOp::Insert => "insert",
+            DiffOp::Delete => "delete",
+        }
+    }
+}
+
+// An edit along with the old and new line indices it refers to
+type Edit = (DiffOp, Option<usize>, Option<usize>);
+
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+// Drop ANSI escape sequences so colours don't make otherwise equal lines differ
+pub fn strip_ansi(line: &str) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            output.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI sequences end with a byte in the @ to ~ range
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC sequences end with BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    output
+}
+
+pub fn diff(old: &str, new: &str, ignore_ansi: bool) -> Result<Vec<DiffLine>, PastebinError> {
+    if old.len() > MAX_BYTES || new.len() > MAX_BYTES {
+        return Err(PastebinError::TooBig("These are too big to compare!".to_string()));
+    }
+
+    let prepare = |content: &str| -> Vec<String> {
+        content
+            .lines()
+            .map(|line| if ignore_ansi { strip_ansi(line) } else { line.to_string() })
+            .collect()
+    };
+
+    let old = prepare(old);
+    let new = prepare(new);
+
+    if old.len() > MAX_LINES || new.len() > MAX_LINES {
+        return Err(PastebinError::TooBig("These have too many lines to compare!".to_string()));
+    }
+
+    let edits = myers(&old, &new).ok_or_else(|| {
+        PastebinError::Validation("These are too different to compare line by line!".to_string())
+    })?;
+
+    Ok(edits
+        .into_iter()
+        .map(|(op, old_index, new_index)| DiffLine {
+            op,
+            old_line: old_index.map(|i| i + 1),
+            new_line: new_index.map(|i| i + 1),
+            text: match (old_index, new_index) {
+                (Some(i), _) => old[i].clone(),
+                (None, Some(j)) => new[j].clone(),
+                (None, None) => String::new(),
+            },
+        })
+        .collect())
+}
+
+// Myers' O((N+M)D) shortest edit script, gives up past MAX_EDITS
+fn myers(a: &[String], b: &[String]) -> Option<Vec<Edit>> {
+    let n = a.len() as i64;
+    let m = b.len() as i64;
+    let limit = (a.len() + b.len()).min(MAX_EDITS) as i64;
+    let offset = limit + 1;
+    let index = |k: i64| (k + offset) as usize;
+
+    let mut v = vec![0i64; 2 * limit as usize + 3];
+    let mut trace: Vec<Vec<i64>> = Vec::new();
+    let mut done = false;
+
+    for d in 0..=limit {
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[index(k)] = x;
+
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+
+            k += 2;
+        }
+
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        if done {
+            break;
+        }
+    }
+
+    if !done {
+        return None;
+    }
+
+    // Walk back through each round to recover the edits
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for d in (1..trace.len() as i64).rev() {
+        let previous = &trace[(d - 1) as usize];
+        let get = |k: i64| previous[(k + d - 1) as usize];
+
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = get(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push((DiffOp::Equal, Some(x as usize), Some(y as usize)));
+        }
+
+        if x == previous_x {
+            edits.push((DiffOp::Insert, None, Some(previous_y as usize)));
+        } else {
+            edits.push((DiffOp::Delete, Some(previous_x as usize), None));
+        }
+
+        x = previous_x;
+        y = previous_y;
+    }
+
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push((DiffOp::Equal, Some(x as usize), Some(y as usize)));
+    }
+
+    edits.reverse();
+    Some(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, strip_ansi, DiffOp};
+
+    // One op and the line's text per line, in order
+    fn ops(old: &str, new: &str) -> Vec<(DiffOp, String)> {
+        diff(old, new, false)
+            .unwrap()
+            .into_iter()
+            .map(|line| (line.op, line.text))
+            .collect()
+    }
+
+    fn line(op: DiffOp, text: &str) -> (DiffOp, String) {
+        (op, text.to_string())
+    }
+
+    #[test]
+    fn identical_input_is_all_equal() {
+        let lines = diff("one\ntwo\nthree", "one\ntwo\nthree", false).unwrap();
+        assert_eq!(lines.len(), 3);
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line.op, DiffOp::Equal);
+            assert_eq!(line.old_line, Some(i + 1));
+            assert_eq!(line.new_line, Some(i + 1));
+        }
+    }
+
+    #[test]
+    fn pure_insert() {
+        assert_eq!(
+            ops("one\nthree", "one\ntwo\nthree"),
+            vec![
+                line(DiffOp::Equal, "one"),
+                line(DiffOp::Insert, "two"),
+                line(DiffOp::Equal, "three"),
+            ]
+        );
+
+        let lines = diff("one\nthree", "one\ntwo\nthree", false).unwrap();
+        assert_eq!((lines[1].old_line, lines[1].new_line), (None, Some(2)));
+        assert_eq!((lines[2].old_line, lines[2].new_line), (Some(2), Some(3)));
+    }
+
+    #[test]
+    fn pure_delete() {
+        assert_eq!(
+            ops("one\ntwo\nthree", "one\nthree"),
+            vec![
+                line(DiffOp::Equal, "one"),
+                line(DiffOp::Delete, "two"),
+                line(DiffOp::Equal, "three"),
+            ]
+        );
+
+        let lines = diff("one\ntwo\nthree", "one\nthree", false).unwrap();
+        assert_eq!((lines[1].old_line, lines[1].new_line), (Some(2), None));
+    }
+
+    #[test]
+    fn replaced_line() {
+        let lines = ops("one\ntwo\nthree", "one\n2\nthree");
+        assert_eq!(lines.len(), 4);
+        assert!(lines.contains(&line(DiffOp::Delete, "two")));
+        assert!(lines.contains(&line(DiffOp::Insert, "2")));
+        assert_eq!(lines[0], line(DiffOp::Equal, "one"));
+        assert_eq!(lines[3], line(DiffOp::Equal, "three"));
+    }
+
+    #[test]
+    fn trailing_newline_makes_no_difference() {
+        assert_eq!(
+            ops("one\ntwo", "one\ntwo\n"),
+            vec![line(DiffOp::Equal, "one"), line(DiffOp::Equal, "two")]
+        );
+    }
+
+    #[test]
+    fn empty_sides() {
+        assert!(ops("", "").is_empty());
+        assert_eq!(
+            ops("", "one\ntwo"),
+            vec![line(DiffOp::Insert, "one"), line(DiffOp::Insert, "two")]
+        );
+        assert_eq!(
+            ops("one\ntwo", ""),
+            vec![line(DiffOp::Delete, "one"), line(DiffOp::Delete, "two")]
+        );
+    }
+
+    #[test]
+    fn ignores_colours_when_asked() {
+        let old = "\x1b[31mred\x1b[0m";
+        assert_eq!(diff(old, "red", false).unwrap().len(), 2);
+
+        let lines = diff(old, "red", true).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].op, DiffOp::Equal);
+        assert_eq!(strip_ansi("\x1b]0;title\x07plain"), "plain");
+    }
+
+    #[test]
+    fn gives_up_on_very_different_content() {
+        let old: String = (0..3000).map(|i| format!("old {}\n", i)).collect();
+        let new: String = (0..3000).map(|i| format!("new {}\n", i)).collect();
+        assert!(diff(&old, &new, false).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::paste::PasteFormat;
+use crate::paste::PasteVisibility;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -8,6 +8,17 @@ pub enum ValidDestination {
     GDrive,
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidExpiry {
+    #[default]
+    Never,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
 #[derive(Deserialize)]
 pub struct PasteForm {
     pub csrf_token: String,
@@ -16,8 +27,32 @@ pub struct PasteForm {
     pub content: String,
     pub title: Option<String>,
     pub tags: Option<String>,
-    pub format: PasteFormat,
+    pub format: String,
     pub destination: ValidDestination,
+    #[serde(default)]
+    pub expiry: ValidExpiry,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+    #[serde(default)]
+    pub visibility: PasteVisibility,
+    pub password: Option<String>,
+    // Forks of locked pastes keep the lock they came with
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    pub slug: Option<String>,
+    pub language: Option<String>,
+    #[serde(skip)]
+    pub forked_from: Option<String>,
+    #[serde(skip)]
+    pub files: Vec<PasteFileForm>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PasteFileForm {
+    pub name: String,
+    pub content: String,
+    pub format: String,
+    pub language: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -26,6 +61,26 @@ pub struct PasteEditForm {
 
     pub title: Option<String>,
     pub tags: Option<String>,
+    pub content: Option<String>,
+    pub slug: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasteForkForm {
+    pub csrf_token: String,
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasteRevealForm {
+    pub csrf_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasteUnlockForm {
+    pub csrf_token: String,
+
+    pub password: String,
 }
 
 #[derive(Deserialize)]
@@ -35,8 +90,40 @@ pub struct PasteDeleteForm {
 
 #[derive(Deserialize)]
 pub struct PasteAPIForm {
+    #[serde(default)]
     pub content: String,
     pub title: Option<String>,
     pub tags: Option<String>,
-    pub format: PasteFormat,
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub expiry: ValidExpiry,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+    #[serde(default)]
+    pub visibility: PasteVisibility,
+    pub password: Option<String>,
+    pub slug: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub files: Vec<PasteFileForm>,
+}
+
+#[derive(Deserialize)]
+pub struct APITokenForm {
+    pub csrf_token: String,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct APITokenRevokeForm {
+    pub csrf_token: String,
+}
+
+// Anything left out of an API edit stays as it is
+#[derive(Deserialize)]
+pub struct PasteAPIEditForm {
+    pub title: Option<String>,
+    pub tags: Option<String>,
+    pub content: Option<String>,
 }
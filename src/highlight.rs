@@ -0,0 +1,364 @@
+use crate::ansi;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    JavaScript,
+    Lua,
+    Python,
+    Rust,
+}
+
+pub const LANGUAGES: [Language; 4] = [
+    Language::JavaScript,
+    Language::Lua,
+    Language::Python,
+    Language::Rust,
+];
+
+struct Syntax {
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comment: &'static str,
+    block_comment: Option<(&'static str, &'static str)>,
+    // Longest first, a string opener also closes it unless it's a Lua long string
+    strings: &'static [&'static str],
+}
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+        "delete", "do", "else", "export", "extends", "finally", "for", "function", "if", "import",
+        "in", "instanceof", "let", "new", "of", "return", "static", "switch", "throw", "try",
+        "typeof", "var", "void", "while", "yield",
+    ],
+    literals: &["false", "null", "this", "true", "undefined"],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    strings: &["\"", "'", "`"],
+};
+
+const LUA: Syntax = Syntax {
+    keywords: &[
+        "and", "break", "do", "else", "elseif", "end", "for", "function", "goto", "if", "in",
+        "local", "not", "or", "repeat", "return", "then", "until", "while",
+    ],
+    literals: &["false", "nil", "self", "true"],
+    line_comment: "--",
+    block_comment: Some(("--[[", "]]")),
+    strings: &["[[", "\"", "'"],
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+        "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+        "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+    ],
+    literals: &["False", "None", "True", "self"],
+    line_comment: "#",
+    block_comment: None,
+    strings: &["\"\"\"", "'''", "\"", "'"],
+};
+
+// Single quotes are left alone in Rust, lifetimes use them too
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "super", "trait", "type", "unsafe", "use",
+        "where", "while",
+    ],
+    literals: &["false", "None", "self", "Self", "Some", "true"],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    strings: &["\""],
+};
+
+// Tell-tale bits of each language and how much they count towards it
+const HINTS: [(Language, &[(&str, usize)]); 4] = [
+    (
+        Language::JavaScript,
+        &[
+            ("const ", 3),
+            ("let ", 2),
+            ("var ", 2),
+            ("=>", 2),
+            ("===", 3),
+            ("!==", 3),
+            ("console.", 3),
+            ("document.", 3),
+            ("this.", 2),
+            ("nexusclient", 4),
+            ("};", 1),
+        ],
+    ),
+    (
+        Language::Lua,
+        &[
+            ("local ", 3),
+            (" then", 3),
+            ("elseif ", 3),
+            ("~=", 3),
+            ("nil", 2),
+            ("end\n", 2),
+            ("..", 1),
+            ("--", 1),
+            ("cecho(", 4),
+            ("tempTimer(", 4),
+            ("tempTrigger(", 4),
+            ("matches[", 3),
+        ],
+    ),
+    (
+        Language::Python,
+        &[
+            ("def ", 3),
+            ("elif ", 3),
+            ("self.", 2),
+            ("None", 2),
+            ("True", 2),
+            ("False", 2),
+            ("print(", 2),
+            ("):\n", 3),
+            ("lambda ", 2),
+            ("__init__", 4),
+            ("import ", 1),
+        ],
+    ),
+    (
+        Language::Rust,
+        &[
+            ("fn ", 3),
+            ("let mut ", 4),
+            ("impl ", 3),
+            ("pub ", 2),
+            ("::", 2),
+            ("&self", 3),
+            ("&str", 3),
+            ("Vec<", 3),
+            ("Option<", 3),
+            ("#[derive", 5),
+            ("println!", 4),
+            ("->", 1),
+        ],
+    ),
+];
+
+impl Language {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Language::JavaScript => "js",
+            Language::Lua => "lua",
+            Language::Python => "python",
+            Language::Rust => "rust",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::JavaScript => "JavaScript",
+            Language::Lua => "Lua",
+            Language::Python => "Python",
+            Language::Rust => "Rust",
+        }
+    }
+
+    pub fn ext(&self) -> &'static str {
+        match self {
+            Language::JavaScript => "js",
+            Language::Lua => "lua",
+            Language::Python => "py",
+            Language::Rust => "rs",
+        }
+    }
+
+    // Takes the usual names people give these along with our own keys
+    pub fn from_hint(hint: &str) -> Option<Self> {
+        match hint.trim().to_ascii_lowercase().as_str() {
+            "js" | "javascript" => Some(Language::JavaScript),
+            "lua" => Some(Language::Lua),
+            "py" | "python" => Some(Language::Python),
+            "rs" | "rust" => Some(Language::Rust),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &'static Syntax {
+        match self {
+            Language::JavaScript => &JAVASCRIPT,
+            Language::Lua => &LUA,
+            Language::Python => &PYTHON,
+            Language::Rust => &RUST,
+        }
+    }
+}
+
+/// Guess the language of some code, None if nothing stands out
+pub fn detect(content: &str) -> Option<Language> {
+    let mut best = None;
+    let mut best_score = 0;
+
+    for (language, hints) in HINTS.iter() {
+        let score: usize = hints
+            .iter()
+            .map(|(hint, weight)| content.matches(hint).count().min(10) * weight)
+            .sum();
+        if score > best_score {
+            best = Some(*language);
+            best_score = score;
+        }
+    }
+
+    // A couple of stray matches isn't enough to go on
+    if best_score < 6 {
+        return None;
+    }
+    best
+}
+
+/// Render code as highlighted HTML, one span per line so we get line numbers
+pub fn to_html(content: &str, language: Option<Language>) -> String {
+    let content = content.replace("\r\n", "\n");
+    let content = content.strip_suffix('\n').unwrap_or(&content);
+
+    let tokens = match language {
+        Some(language) => tokenize(content, language.syntax()),
+        None => vec![(None, content.to_string())],
+    };
+
+    let mut output = String::with_capacity(content.len() * 2);
+    output.push_str("<span class=\"line\">");
+    for (class, text) in tokens.iter() {
+        for (n, part) in text.split('\n').enumerate() {
+            if n > 0 {
+                output.push_str("</span>\n<span class=\"line\">");
+            }
+            if part.is_empty() {
+                continue;
+            }
+            match class {
+                Some(class) => {
+                    output.push_str(&format!("<span class=\"hl-{}\">{}</span>", class, ansi::escape(part)))
+                }
+                None => output.push_str(&ansi::escape(part)),
+            }
+        }
+    }
+    output.push_str("</span>");
+
+    output
+}
+
+type Token = (Option<&'static str>, String);
+
+fn tokenize(content: &str, syntax: &Syntax) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut plain = String::new();
+    let mut rest = content;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some((open, close)) = syntax.block_comment {
+            if rest.starts_with(open) {
+                let end = rest[open.len()..]
+                    .find(close)
+                    .map(|i| open.len() + i + close.len())
+                    .unwrap_or(rest.len());
+                push(&mut tokens, &mut plain, "comment", &rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+        }
+
+        if rest.starts_with(syntax.line_comment) {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            push(&mut tokens, &mut plain, "comment", &rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if let Some(quote) = syntax.strings.iter().find(|quote| rest.starts_with(**quote)) {
+            let end = string_end(rest, quote);
+            push(&mut tokens, &mut plain, "string", &rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let after_word = plain.chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+        if c.is_ascii_digit() && !after_word {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            // Don't swallow Lua's .. or ranges like 0..10
+            let end = rest[..end].find("..").unwrap_or(end);
+            push(&mut tokens, &mut plain, "number", &rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if (c.is_alphabetic() || c == '_') && !after_word {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+
+            if syntax.keywords.contains(&word) {
+                push(&mut tokens, &mut plain, "keyword", word);
+            } else if syntax.literals.contains(&word) {
+                push(&mut tokens, &mut plain, "literal", word);
+            } else if rest[end..].trim_start_matches([' ', '\t']).starts_with(['(', '!']) {
+                push(&mut tokens, &mut plain, "function", word);
+            } else {
+                plain.push_str(word);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if !plain.is_empty() {
+        tokens.push((None, plain));
+    }
+
+    tokens
+}
+
+fn push(tokens: &mut Vec<Token>, plain: &mut String, class: &'static str, text: &str) {
+    if !plain.is_empty() {
+        tokens.push((None, std::mem::take(plain)));
+    }
+    tokens.push((Some(class), text.to_string()));
+}
+
+// Where a string starting at the beginning of input ends, quotes included
+fn string_end(input: &str, quote: &str) -> usize {
+    let close = if quote == "[[" { "]]" } else { quote };
+    let multiline = quote.len() > 1 || quote == "`";
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices().skip(quote.len()) {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        if c == '\\' && quote != "[[" {
+            escaped = true;
+            continue;
+        }
+
+        // Unterminated single line strings stop at the end of the line
+        if c == '\n' && !multiline {
+            return i;
+        }
+
+        if input[i..].starts_with(close) {
+            return i + close.len();
+        }
+    }
+
+    input.len()
+}
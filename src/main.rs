@@ -1,11 +1,14 @@
 use axum::{
     body::Body,
-    extract::{DefaultBodyLimit, Form, Path, Query, State},
-    http::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION},
+    extract::{ConnectInfo, DefaultBodyLimit, Form, Multipart, Path, Query, State},
+    http::header::{
+        AUTHORIZATION, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_SECURITY_POLICY,
+        CONTENT_TYPE, LOCATION, VARY,
+    },
     http::{HeaderMap, Method, StatusCode},
     middleware,
     response::{IntoResponse, Json, Redirect, Response},
-    routing::{delete, get, post},
+    routing::{get, post},
     Router,
 };
 use axum_csrf::{CsrfConfig, CsrfLayer, CsrfToken};
@@ -13,27 +16,37 @@ use serde::Serialize;
 use sqlx::postgres::PgPool;
 use std::collections::HashMap;
 use std::env;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio_util::io::ReaderStream;
 use tower_cookies::{CookieManagerLayer, Cookies, Key};
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{error, info};
 
+mod ansi;
 mod api;
 mod cloudflare;
+mod compression;
 mod config;
+mod diff;
 mod discord;
 mod errors;
 mod forms;
 mod gdrive;
+mod highlight;
+mod markdown;
 mod oauth;
+mod password;
 mod paste;
 mod recaptcha;
 mod runtime;
 mod s3;
+mod sanitize;
 mod session;
 mod static_files;
 mod templates;
+mod uploads;
 mod utils;
 
 #[tokio::main]
@@ -74,11 +87,14 @@ async fn main() -> Result<(), anyhow::Error> {
     tokio::spawn(async move {
         tokio::join!(
             paste::update_views(&timer_state, true),
+            paste::reap_expired(&timer_state, true),
+            uploads::reap_stale(&timer_state, true),
             cloudflare::cleanup_cache(&timer_state, true, true),
         );
     });
 
     tokio::spawn(api::reset_api());
+    tokio::spawn(password::prune_attempts());
 
     let shutdown_state = shared_state.clone();
     tokio::spawn(async move {
@@ -108,7 +124,7 @@ async fn main() -> Result<(), anyhow::Error> {
         .with_lifetime(time::Duration::seconds(0));
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::DELETE, Method::POST])
+        .allow_methods([Method::DELETE, Method::GET, Method::POST, Method::PUT])
         .allow_headers([AUTHORIZATION, CONTENT_TYPE])
         .allow_origin([
             // FIXME: this ought to be configurable
@@ -122,12 +138,33 @@ async fn main() -> Result<(), anyhow::Error> {
     // build our application with routes
     let app = Router::new()
         .route("/pastebin/api/v1/create", post(api::create))
-        .route("/pastebin/api/v1/{paste_id}", delete(api::delete))
+        .route("/pastebin/api/v1/uploads", post(api::start_upload))
+        .route(
+            "/pastebin/api/v1/uploads/{upload_id}",
+            get(api::upload_status).put(api::upload_chunk).delete(api::abort_upload),
+        )
+        .route("/pastebin/api/v1/uploads/{upload_id}/finish", post(api::finish_upload))
+        .route("/pastebin/api/v1/pastes", get(api::list))
+        .route("/pastebin/api/v1/{paste_id}", get(api::get).patch(api::update).delete(api::delete))
+        .route("/pastebin/api/v1/{paste_id}/content", get(api::content))
         .layer(cors)
         .route("/pastebin/api/v1/about", get(api::about))
         .route("/pastebin/", get(pastebin).post(newpaste))
         .route("/pastebin/{paste_id}", get(getpaste).patch(editpaste).delete(delpaste))
+        .route("/pastebin/{paste_id}/unlock", post(unlockpaste))
+        .route("/pastebin/{paste_id}/reveal", post(revealpaste))
+        .route("/pastebin/{paste_id}/fork", post(forkpaste))
+        .route("/pastebin/{paste_id}/raw", get(getraw))
+        .route("/pastebin/{paste_id}/export", get(exportpaste))
+        .route("/pastebin/{paste_id}/attachments", post(attachpaste))
+        .route("/pastebin/{paste_id}/attachments/{position}", get(getattachment))
+        .route("/pastebin/{paste_id}/files/{position}", get(getfile))
+        .route("/pastebin/{paste_id}/revisions", get(getrevisions))
+        .route("/pastebin/{paste_id}/revisions/{revision}", get(getrevision))
+        .route("/pastebin/diff/{a}/{b}", get(diffpastes))
         .route("/pastebin/auth/logout", post(logout))
+        .route("/pastebin/account/tokens", get(api::tokens).post(api::create_token))
+        .route("/pastebin/account/tokens/{token_id}/revoke", post(api::revoke_token))
         .layer(DefaultBodyLimit::max(32 * 1024 * 1024)) // 32MB is a lot of log!
         .layer(CsrfLayer::new(csrf_config))
         .route("/pastebin/auth/discord/start", get(discord::start))
@@ -135,8 +172,9 @@ async fn main() -> Result<(), anyhow::Error> {
         .route("/pastebin/auth/gdrive/start", get(gdrive::auth_start))
         .route("/pastebin/auth/gdrive/finish", get(gdrive::auth_finish))
         .route("/pastebin/about", get(about))
+        .route("/pastebin/account/usage", get(usage))
         .route("/pastebin/search/", get(search))
-        .route("/pastebinc/{paste_id}/content", get(getdrivecontent))
+        .route("/pastebinc/{paste_id}/content", get(getcontent))
         .layer(CookieManagerLayer::new())
         .layer(middleware::from_fn_with_state(
             shared_state.clone(),
@@ -157,7 +195,7 @@ async fn main() -> Result<(), anyhow::Error> {
     // run it
     let listener = tokio::net::TcpListener::bind(bind_addr).await?;
     info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
@@ -182,6 +220,35 @@ async fn about(
     templates::HtmlTemplate(template)
 }
 
+async fn usage(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+) -> Result<Response, errors::PastebinError> {
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Ok(Redirect::to("/pastebin/auth/discord/start").into_response()),
+    };
+
+    let usage = paste::get_usage(&state.db, &user_id).await?;
+    let (tier, limits) = state.config.quotas.tier(&user_id);
+    let percent = |used: i64, max: Option<i64>| max.map(|max| (used * 100 / max).min(100)).unwrap_or(0);
+
+    let template = templates::AccountUsageTemplate {
+        static_domain: state.config.static_domain.clone(),
+        tier: tier.to_string(),
+        pastes: usage.pastes,
+        max_pastes: limits.max_pastes,
+        pastes_percent: percent(usage.pastes, limits.max_pastes),
+        bytes: utils::human_size(usage.bytes),
+        max_bytes: limits.max_bytes.map(utils::human_size),
+        bytes_percent: percent(usage.bytes, limits.max_bytes),
+        user_id: Some(user_id),
+    };
+
+    Ok(templates::HtmlTemplate(template).into_response())
+}
+
 async fn pastebin(
     State(state): State<Arc<runtime::AppState>>,
     cookies: Cookies,
@@ -258,29 +325,465 @@ async fn getpaste(
     token: CsrfToken,
     Path(paste_id): Path<String>,
 ) -> Result<Response, errors::PastebinError> {
-    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+    match renderpaste(&state, &cookies, token, &paste_id, None, false).await {
+        // Renamed pastes still answer to their old paste ID
+        Err(errors::PastebinError::NotFound(message)) => match paste::Paste::renamed_to(&state.db, &paste_id).await? {
+            Some(renamed) => Ok(Redirect::permanent(&format!("/pastebin/{}", renamed)).into_response()),
+            None => Err(errors::PastebinError::NotFound(message)),
+        },
+        response => response,
+    }
+}
+
+// Burnable pastes only burn on this deliberate POST, link previews and prefetches just GET the page
+async fn revealpaste(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    token: CsrfToken,
+    Path(paste_id): Path<String>,
+    Form(payload): Form<forms::PasteRevealForm>,
+) -> Result<Response, errors::PastebinError> {
+    // Verify the CSRF token
+    if token.verify(&payload.csrf_token).is_err() {
+        return Err(errors::PastebinError::Auth("CSRF token is not valid!".to_string()));
+    }
+
+    renderpaste(&state, &cookies, token, &paste_id, None, true).await
+}
+
+// A revision that isn't a number is a mistake, not a request for the latest one
+fn revision_param(params: &HashMap<String, String>) -> Result<Option<i32>, errors::PastebinError> {
+    params
+        .get("revision")
+        .map(|revision| {
+            revision
+                .parse::<i32>()
+                .map_err(|_| errors::PastebinError::Validation("Invalid revision".to_string()))
+        })
+        .transpose()
+}
+
+async fn getrevision(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    token: CsrfToken,
+    Path((paste_id, revision)): Path<(String, i32)>,
+) -> Result<Response, errors::PastebinError> {
+    renderpaste(&state, &cookies, token, &paste_id, Some(revision), false).await
+}
+
+async fn renderpaste(
+    state: &Arc<runtime::AppState>,
+    cookies: &Cookies,
+    token: CsrfToken,
+    paste_id: &str,
+    revision: Option<i32>,
+    reveal: bool,
+) -> Result<Response, errors::PastebinError> {
+    let mut paste = paste::Paste::get(&state.db, paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(state, cookies);
+    if !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
 
     // Verify ownership
-    let (user_id, _) = utils::get_user_id(&state, &cookies);
-    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    let mut owned = session::is_paste_in_session(state, cookies, paste_id);
     if user_id.is_some() && user_id == paste.user_id {
         owned = true;
     }
 
-    let views = paste.get_views().await;
+    // Locked pastes need their password before we reveal anything
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(state, cookies, &paste.paste_id)
+    {
+        let template = templates::PasteLockedTemplate {
+            static_domain: state.config.static_domain.clone(),
+            csrf_token: token.authenticity_token().unwrap(),
+            user_id,
+            paste,
+            error: String::new(),
+        };
+        return Ok((token, templates::HtmlTemplate(template)).into_response());
+    }
+
+    // Older revisions are rendered just like the paste, only pointing at their own content
+    let latest_revision = paste.revision;
+    if let Some(revision) = revision {
+        if paste.burn_after_reading {
+            return Err(errors::PastebinError::NotFound("Revision not found".to_string()));
+        }
+
+        let revision = paste.get_revision(&state.db, revision).await?;
+        paste.revision = revision.revision;
+        paste.s3_key = revision.s3_key;
+        paste.content_encoding = revision.content_encoding;
+    }
+
+    // Everyone but the owner has to ask for a burnable paste before it burns
+    if paste.burn_after_reading && !owned && !reveal {
+        let template = templates::PasteBurnTemplate {
+            static_domain: state.config.static_domain.clone(),
+            csrf_token: token.authenticity_token().unwrap(),
+            user_id,
+            paste,
+        };
+        return Ok((token, templates::HtmlTemplate(template)).into_response());
+    }
+
+    // Burnable pastes are served inline, the first non-owner view burns them
+    let (views, content, content_url) = if paste.burn_after_reading {
+        let content = if owned {
+            paste.get_content(state).await?
+        } else {
+            paste.burn(state).await?
+        };
+        (paste.views, Some(content), String::new())
+    } else {
+        let views = paste.get_views().await;
+        (views, None, paste.get_content_url(&state.config.s3_bucket_url))
+    };
+
+    // Markdown and code only ever get rendered on our side, ANSI logs are too for anyone without Javascript
+    // and for link previews, unless they're so big the export is the better bet
+    let rendered = if paste.format.renders_server_side() || paste.format.is_terminal() {
+        let content = match &content {
+            Some(content) => Ok(content.clone()),
+            None => paste.get_content(state).await,
+        };
+        match content {
+            Ok(content) if paste.format.is_terminal() && content.len() > paste::MAX_RENDERED_LOG => None,
+            Ok(content) => paste.render_html(content).await,
+            Err(err) => {
+                error!("Failed to fetch content for rendering {}: {}", paste.paste_id, err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Bundles show the rest of their files in tabs next to the first one, each loaded once it's opened
+    let files = paste.get_files(&state.db).await?;
+
+    let attachments = paste
+        .get_attachments(&state.db)
+        .await?
+        .into_iter()
+        .map(|attachment| {
+            let url = paste.get_attachment_url(&attachment, &state.config.s3_bucket_url);
+            (attachment, url)
+        })
+        .collect();
+
+    let forks = paste.get_forks(&state.db).await?;
+
+    // Don't point at a source the viewer isn't allowed to see
+    if let Some(forked_from) = &paste.forked_from {
+        let visible = match paste::Paste::get(&state.db, forked_from).await {
+            Ok(source) => source.can_view(&user_id),
+            Err(errors::PastebinError::NotFound(_)) => false,
+            Err(err) => return Err(err),
+        };
+        if !visible {
+            paste.forked_from = None;
+        }
+    }
+
+    let recaptcha_key = if state.config.recaptcha_enabled {
+        state.config.recaptcha_key.clone()
+    } else {
+        String::new()
+    };
+
     let template = templates::PasteTemplate {
         static_domain: state.config.static_domain.clone(),
-        content_url: paste.get_content_url(&state.config.s3_bucket_url),
+        recaptcha_key,
+        content_url,
         csrf_token: token.authenticity_token().unwrap(),
         user_id,
         paste,
         views,
         owned,
+        content,
+        rendered,
+        latest_revision,
+        forks,
+        files,
+        attachments,
     };
 
     Ok((token, templates::HtmlTemplate(template)).into_response())
 }
 
+async fn getrevisions(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    Path(paste_id): Path<String>,
+) -> Result<Response, errors::PastebinError> {
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if paste.burn_after_reading || !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Verify ownership
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+
+    // Send them over to the unlock form for locked pastes
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &paste.paste_id)
+    {
+        return Ok(Redirect::to(&format!("/pastebin/{}", paste.paste_id)).into_response());
+    }
+
+    let revisions = paste.get_revisions(&state.db).await?;
+
+    let template = templates::PasteRevisionsTemplate {
+        static_domain: state.config.static_domain.clone(),
+        user_id,
+        paste,
+        revisions,
+    };
+
+    Ok(templates::HtmlTemplate(template).into_response())
+}
+
+// Each side of a diff is either a paste_id or a paste_id@revision
+async fn getdiffside(
+    state: &Arc<runtime::AppState>,
+    cookies: &Cookies,
+    side: &str,
+) -> Result<paste::Paste, errors::PastebinError> {
+    let (paste_id, revision) = match side.rsplit_once('@') {
+        Some((paste_id, revision)) => match revision.parse::<i32>() {
+            Ok(revision) => (paste_id, Some(revision)),
+            Err(_) => return Err(errors::PastebinError::Validation("Invalid revision".to_string())),
+        },
+        None => (side, None),
+    };
+
+    let mut paste = paste::Paste::get(&state.db, paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(state, cookies);
+    if paste.burn_after_reading || !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Verify ownership
+    let mut owned = session::is_paste_in_session(state, cookies, paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(state, cookies, &paste.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    // We never get to see what's inside encrypted pastes
+    if paste.format.is_encrypted() {
+        return Err(errors::PastebinError::Validation("Encrypted pastes can't be compared!".to_string()));
+    }
+
+    if let Some(revision) = revision {
+        let revision = paste.get_revision(&state.db, revision).await?;
+        paste.revision = revision.revision;
+        paste.s3_key = revision.s3_key;
+        paste.content_encoding = revision.content_encoding;
+    }
+
+    Ok(paste)
+}
+
+async fn diffpastes(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    Path((a, b)): Path<(String, String)>,
+) -> Result<Response, errors::PastebinError> {
+    let old = getdiffside(&state, &cookies, &a).await?;
+    let new = getdiffside(&state, &cookies, &b).await?;
+
+    let (old_content, new_content) = tokio::try_join!(old.get_content(&state), new.get_content(&state))?;
+
+    // Colours shouldn't count as changes in ANSI logs
+    let ignore_ansi = old.format.is_terminal() || new.format.is_terminal();
+    let lines = match tokio::task::spawn_blocking(move || diff::diff(&old_content, &new_content, ignore_ansi)).await {
+        Ok(lines) => lines?,
+        Err(err) => {
+            error!("Diff task failed: {}", err);
+            return Err(errors::PastebinError::Internal(err.to_string()));
+        }
+    };
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let template = templates::DiffTemplate {
+        static_domain: state.config.static_domain.clone(),
+        user_id,
+        old,
+        new,
+        lines,
+    };
+
+    Ok(templates::HtmlTemplate(template).into_response())
+}
+
+async fn forkpaste(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    cookies: Cookies,
+    token: CsrfToken,
+    Path(paste_id): Path<String>,
+    Form(payload): Form<forms::PasteForkForm>,
+) -> Result<Response, errors::PastebinError> {
+    let (user_id, session_id) = utils::get_user_id(&state, &cookies);
+
+    // Verify the CSRF token
+    if token.verify(&payload.csrf_token).is_err() {
+        return Err(errors::PastebinError::Auth("CSRF token is not valid!".to_string()));
+    }
+
+    let source = paste::Paste::get(&state.db, &paste_id).await?;
+
+    // Burnable pastes are gone once read, there's nothing left to fork
+    if source.burn_after_reading || !source.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Verify ownership
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == source.user_id {
+        owned = true;
+    }
+    if source.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &source.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    // Verify the recaptcha response
+    let score = recaptcha::verify(&state.config.recaptcha_secret, "paste", &payload.token)
+        .await
+        .unwrap_or_else(|err| {
+            error!("Error verifying recaptcha: {}", err);
+            0.0
+        });
+
+    // Copy the content over on our side, the browser never has to send it back
+    let content = source.get_content(&state).await?;
+    let mut files = Vec::new();
+    for file in source.get_files(&state.db).await? {
+        files.push(forms::PasteFileForm {
+            content: if file.position == 0 {
+                content.clone()
+            } else {
+                source.get_file_content(&state, &file).await?
+            },
+            name: file.name,
+            format: file.format.key(),
+            language: None,
+        });
+    }
+
+    let form = forms::PasteForm {
+        content,
+        title: source.title.clone(),
+        tags: source.tags.as_ref().map(|tags| tags.join(" ")),
+        format: source.format.key(),
+        destination: forms::ValidDestination::DataStore,
+        expiry: forms::ValidExpiry::Never,
+        burn_after_reading: false,
+        visibility: source.visibility,
+        password: None,
+        password_hash: source.password_hash.clone(),
+        slug: None,
+        language: None,
+        forked_from: Some(source.paste_id.clone()),
+        files,
+        csrf_token: payload.csrf_token,
+        token: payload.token,
+    };
+
+    // Create the paste
+    let paste_id = paste::new_paste(&state, &form, score, user_id, session_id, "").await?;
+
+    // Update the session with the new paste_id
+    session::update_session(&state, &cookies, &paste_id);
+
+    // Check for the presence of the X-Requested-With header
+    if headers.contains_key("X-Requested-With") {
+        Ok((StatusCode::OK, paste_id).into_response())
+    } else {
+        Ok((
+            StatusCode::SEE_OTHER,
+            [(LOCATION, format!("/pastebin/{}", paste_id))],
+            "",
+        )
+            .into_response())
+    }
+}
+
+async fn unlockpaste(
+    State(state): State<Arc<runtime::AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    cookies: Cookies,
+    token: CsrfToken,
+    Path(paste_id): Path<String>,
+    Form(payload): Form<forms::PasteUnlockForm>,
+) -> Result<Response, errors::PastebinError> {
+    // Verify the CSRF token
+    if token.verify(&payload.csrf_token).is_err() {
+        return Err(errors::PastebinError::Auth("CSRF token is not valid!".to_string()));
+    }
+
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    if let Some(password_hash) = &paste.password_hash {
+        // Guessing is slow and there's only so many goes at it
+        let client = utils::client_ip(&state, &headers, addr);
+        if !password::can_attempt(&paste.paste_id, &client).await {
+            return Err(errors::PastebinError::TooMany(
+                "Too many wrong passwords, try again in a bit!".to_string(),
+            ));
+        }
+
+        if !password::verify(&payload.password, password_hash).await {
+            password::record_failure(&paste.paste_id, &client).await;
+            let template = templates::PasteLockedTemplate {
+                static_domain: state.config.static_domain.clone(),
+                csrf_token: token.authenticity_token().unwrap(),
+                user_id,
+                paste,
+                error: "Oop, that's not the right password!".to_string(),
+            };
+            return Ok((StatusCode::UNAUTHORIZED, token, templates::HtmlTemplate(template)).into_response());
+        }
+
+        session::update_unlocked(&state, &cookies, &paste.paste_id);
+    }
+
+    Ok((
+        StatusCode::SEE_OTHER,
+        [(LOCATION, format!("/pastebin/{}", paste.paste_id))],
+        "",
+    )
+        .into_response())
+}
+
 async fn editpaste(
     State(state): State<Arc<runtime::AppState>>,
     cookies: Cookies,
@@ -305,9 +808,21 @@ async fn editpaste(
         return Err(errors::PastebinError::Auth("You don't own this paste!".to_string()));
     }
 
-    paste.edit(&state, &payload.title, &payload.tags).await?;
+    let new_paste_id = paste
+        .edit(&state, &payload.title, &payload.tags, &payload.content, &payload.slug)
+        .await?;
+
+    // Keep anonymous ownership and unlocks working under the new paste_id
+    if new_paste_id != paste_id {
+        if session::is_paste_in_session(&state, &cookies, &paste_id) {
+            session::update_session(&state, &cookies, &new_paste_id);
+        }
+        if session::is_paste_unlocked(&state, &cookies, &paste_id) {
+            session::update_unlocked(&state, &cookies, &new_paste_id);
+        }
+    }
 
-    Ok((StatusCode::OK, paste_id).into_response())
+    Ok((StatusCode::OK, new_paste_id).into_response())
 }
 
 async fn delpaste(
@@ -345,10 +860,12 @@ async fn delpaste(
     }
 }
 
-async fn getdrivecontent(
+async fn getcontent(
     State(state): State<Arc<runtime::AppState>>,
     headers: HeaderMap,
+    cookies: Cookies,
     Path(paste_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<Response, errors::PastebinError> {
     if !headers.contains_key("X-Requested-With") {
         return Ok((
@@ -361,6 +878,24 @@ async fn getdrivecontent(
 
     let paste = paste::Paste::get(&state.db, &paste_id).await?;
 
+    // Burnable pastes are only ever served inline by getpaste, private ones only to their owner
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if paste.burn_after_reading || !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Locked pastes must be unlocked first
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &paste.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
     if let Some(gdrivedl_url) = &paste.gdrivedl {
         let response = match reqwest::get(gdrivedl_url).await {
             Ok(response) => response,
@@ -391,11 +926,349 @@ async fn getdrivecontent(
         let mut our_response = Response::new(Body::from_stream(response.bytes_stream()));
         *our_response.headers_mut() = headers;
         Ok(our_response)
+    } else if paste.keeps_content_private() {
+        let (s3_key, content_encoding) = match revision_param(&params)? {
+            Some(revision) => {
+                let revision = paste.get_revision(&state.db, revision).await?;
+                (revision.s3_key, revision.content_encoding)
+            }
+            None => (paste.s3_key.clone(), paste.content_encoding),
+        };
+
+        let content = s3::get(&state, &s3_key)
+            .await
+            .map_err(|err| errors::PastebinError::Storage(format!("Failed to download from S3: {}", err)))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+        if let Ok(content_type) = paste.get_content_type().parse() {
+            headers.insert(CONTENT_TYPE, content_type);
+        }
+        if content_encoding != compression::Encoding::Identity {
+            headers.insert(CONTENT_ENCODING, content_encoding.as_str().parse().unwrap());
+        }
+
+        Ok((StatusCode::OK, headers, content).into_response())
     } else {
         Err(errors::PastebinError::NotFound("Paste not found".to_string()))
     }
 }
 
+async fn getraw(
+    State(state): State<Arc<runtime::AppState>>,
+    headers: HeaderMap,
+    cookies: Cookies,
+    Path(paste_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, errors::PastebinError> {
+    let mut paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Locked pastes must be unlocked first
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &paste.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    if let Some(revision) = revision_param(&params)? {
+        if paste.burn_after_reading {
+            return Err(errors::PastebinError::NotFound("Revision not found".to_string()));
+        }
+
+        let revision = paste.get_revision(&state.db, revision).await?;
+        paste.revision = revision.revision;
+        paste.s3_key = revision.s3_key;
+        paste.content_encoding = revision.content_encoding;
+    }
+
+    let mut response_headers = HeaderMap::new();
+    let content_type = paste.get_content_type();
+    let content_type = if content_type.starts_with("text/") {
+        format!("{}; charset=utf-8", content_type)
+    } else {
+        content_type
+    };
+    if let Ok(content_type) = content_type.parse() {
+        response_headers.insert(CONTENT_TYPE, content_type);
+    }
+    if let Ok(content_disposition) = format!("inline; filename=\"{}\"", paste.get_filename()).parse() {
+        response_headers.insert(CONTENT_DISPOSITION, content_disposition);
+    }
+
+    // HTML pastes are served from our own origin here, don't let them run anything
+    response_headers.insert(CONTENT_SECURITY_POLICY, "sandbox".parse().unwrap());
+
+    if paste.visibility == paste::PasteVisibility::Public
+        && paste.password_hash.is_none()
+        && !paste.burn_after_reading
+    {
+        response_headers.insert(CACHE_CONTROL, "public, max-age=300".parse().unwrap());
+    } else {
+        response_headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+    }
+
+    // Burnable pastes are only ever burned from their page, where it takes a click
+    if paste.burn_after_reading {
+        if !owned {
+            return Err(errors::PastebinError::Forbidden(
+                "This paste burns after reading, open it on its page to read it!".to_string(),
+            ));
+        }
+        let content = paste.get_content(&state).await?;
+        return Ok((StatusCode::OK, response_headers, content).into_response());
+    }
+
+    if let Some(gdrivedl_url) = &paste.gdrivedl {
+        let response = reqwest::get(gdrivedl_url)
+            .await
+            .map_err(|err| errors::PastebinError::ExternalService(err.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(errors::PastebinError::ExternalService("Google Drive wouldn't talk to us!".to_string()));
+        }
+
+        return Ok((StatusCode::OK, response_headers, Body::from_stream(response.bytes_stream())).into_response());
+    }
+
+    if paste.content_encoding != compression::Encoding::Identity {
+        response_headers.insert(VARY, "Accept-Encoding".parse().unwrap());
+
+        // Not everyone speaks every encoding, those get it decompressed
+        if !utils::accepts_encoding(&headers, paste.content_encoding) {
+            let content = paste.get_content(&state).await?;
+            return Ok((StatusCode::OK, response_headers, content).into_response());
+        }
+
+        response_headers.insert(CONTENT_ENCODING, paste.content_encoding.as_str().parse().unwrap());
+    }
+
+    let content = s3::get_stream(&state, &paste.s3_key)
+        .await
+        .map_err(|err| errors::PastebinError::Storage(format!("Failed to download from S3: {}", err)))?;
+
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        Body::from_stream(ReaderStream::new(content.into_async_read())),
+    )
+        .into_response())
+}
+
+async fn attachpaste(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    token: CsrfToken,
+    Path(paste_id): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Response, errors::PastebinError> {
+    let invalid = |_| errors::PastebinError::Validation("That upload doesn't look right, try again!".to_string());
+
+    let mut csrf_token = String::new();
+    let mut images = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        match field.name() {
+            Some("csrf_token") => csrf_token = field.text().await.map_err(invalid)?,
+            // Browsers send empty file fields along when nothing was picked
+            Some("attachment") if field.file_name().is_some() => {
+                let data = field.bytes().await.map_err(invalid)?;
+                if !data.is_empty() {
+                    images.push(data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Verify the CSRF token
+    if token.verify(&csrf_token).is_err() {
+        return Err(errors::PastebinError::Auth("CSRF token is not valid!".to_string()));
+    }
+
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    // Verify ownership
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if !owned {
+        return Err(errors::PastebinError::Auth("You don't own this paste!".to_string()));
+    }
+
+    paste.attach(&state, images.iter().map(|image| image.as_ref()).collect()).await?;
+
+    Ok((
+        StatusCode::SEE_OTHER,
+        [(LOCATION, format!("/pastebin/{}", paste.paste_id))],
+        "",
+    )
+        .into_response())
+}
+
+async fn getattachment(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    Path((paste_id, position)): Path<(String, i32)>,
+) -> Result<Response, errors::PastebinError> {
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Locked pastes must be unlocked first
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &paste.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    let attachment = paste.get_attachment(&state.db, position).await?;
+
+    let mut response_headers = HeaderMap::new();
+    if let Ok(content_type) = attachment.content_type.parse() {
+        response_headers.insert(CONTENT_TYPE, content_type);
+    }
+    response_headers.insert(CONTENT_SECURITY_POLICY, "sandbox".parse().unwrap());
+    response_headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+
+    let content = s3::get_stream(&state, &attachment.s3_key)
+        .await
+        .map_err(|err| errors::PastebinError::Storage(format!("Failed to download from S3: {}", err)))?;
+
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        Body::from_stream(ReaderStream::new(content.into_async_read())),
+    )
+        .into_response())
+}
+
+// The rest of a bundle's files, rendered for their tab when it's first opened
+async fn getfile(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    Path((paste_id, position)): Path<(String, i32)>,
+) -> Result<Response, errors::PastebinError> {
+    let paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Locked pastes must be unlocked first
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &paste.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    // The first file is the paste itself and it's shown already
+    if position < 1 {
+        return Err(errors::PastebinError::NotFound("File not found".to_string()));
+    }
+
+    let file = paste.get_file(&state.db, position).await?;
+    let content = paste.get_file_content(&state, &file).await?;
+    let rendered = file.render_html(content.clone()).await;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+
+    let template = templates::PasteFileTemplate {
+        file,
+        content,
+        rendered,
+    };
+
+    Ok((headers, templates::HtmlTemplate(template)).into_response())
+}
+
+async fn exportpaste(
+    State(state): State<Arc<runtime::AppState>>,
+    cookies: Cookies,
+    Path(paste_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, errors::PastebinError> {
+    let mut paste = paste::Paste::get(&state.db, &paste_id).await?;
+
+    // Burnable pastes only ever get read once, and that's on the paste page
+    let (user_id, _) = utils::get_user_id(&state, &cookies);
+    if paste.burn_after_reading || !paste.can_view(&user_id) {
+        return Err(errors::PastebinError::NotFound("Paste not found".to_string()));
+    }
+
+    // Locked pastes must be unlocked first
+    let mut owned = session::is_paste_in_session(&state, &cookies, &paste_id);
+    if user_id.is_some() && user_id == paste.user_id {
+        owned = true;
+    }
+    if paste.password_hash.is_some()
+        && !owned
+        && !session::is_paste_unlocked(&state, &cookies, &paste.paste_id)
+    {
+        return Err(errors::PastebinError::Auth("This paste is locked!".to_string()));
+    }
+
+    if let Some(revision) = revision_param(&params)? {
+        let revision = paste.get_revision(&state.db, revision).await?;
+        paste.revision = revision.revision;
+        paste.s3_key = revision.s3_key;
+        paste.content_encoding = revision.content_encoding;
+    }
+
+    let content = paste.get_content(&state).await?;
+    let body = match paste.render_html(content).await {
+        Some(body) => body,
+        None => {
+            return Err(errors::PastebinError::Validation(
+                "This paste can't be exported to HTML!".to_string(),
+            ))
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Ok(content_disposition) = format!("attachment; filename=\"{}.html\"", paste.get_filename()).parse() {
+        headers.insert(CONTENT_DISPOSITION, content_disposition);
+    }
+    headers.insert(
+        CONTENT_SECURITY_POLICY,
+        "default-src 'none'; style-src 'unsafe-inline'".parse().unwrap(),
+    );
+    headers.insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+
+    let template = templates::ExportTemplate {
+        title: paste.get_title(),
+        body,
+        preformatted: paste.format.is_preformatted(),
+    };
+
+    Ok((headers, templates::HtmlTemplate(template)).into_response())
+}
+
 async fn search(
     State(state): State<Arc<runtime::AppState>>,
     headers: HeaderMap,
@@ -0,0 +1,812 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+// Markdown is rendered by us and only ever produces the tags below, everything the
+// author wrote is escaped on the way through so raw HTML never makes it out
+
+// Quotes, lists, emphasis and links nested any deeper than this are left as plain text
+const MAX_NESTING: usize = 16;
+
+// Emphasis closing marks, as the mark and how many of it
+const CLOSERS: [(char, usize); 5] = [('*', 1), ('*', 2), ('_', 1), ('_', 2), ('~', 2)];
+
+/// Render Markdown with GFM tables, fenced code blocks and strikethrough to HTML
+pub fn to_html(input: &str) -> String {
+    let lines: Vec<&str> = input.lines().collect();
+    let mut output = String::with_capacity(input.len() * 2);
+    render_blocks(&lines, false, 0, &mut output);
+    output
+}
+
+fn render_blocks(lines: &[&str], tight: bool, depth: usize, output: &mut String) {
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if trimmed.is_empty() {
+            flush_paragraph(&mut paragraph, tight, output);
+            i += 1;
+            continue;
+        }
+
+        // Setext headings underline the paragraph right above them
+        if !paragraph.is_empty() && indent < 4 && is_setext_underline(trimmed) {
+            let level = if trimmed.starts_with('=') { 1 } else { 2 };
+            output.push_str(&format!("<h{}>{}</h{}>\n", level, inline(&paragraph.join(" ")), level));
+            paragraph.clear();
+            i += 1;
+            continue;
+        }
+
+        if indent < 4 && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            flush_paragraph(&mut paragraph, tight, output);
+            i = render_fence(lines, i, output);
+            continue;
+        }
+
+        if let Some(level) = heading_level(trimmed) {
+            flush_paragraph(&mut paragraph, tight, output);
+            let text = trimmed[level..].trim().trim_end_matches('#').trim_end();
+            output.push_str(&format!("<h{}>{}</h{}>\n", level, inline(text), level));
+            i += 1;
+            continue;
+        }
+
+        if is_rule(trimmed) {
+            flush_paragraph(&mut paragraph, tight, output);
+            output.push_str("<hr>\n");
+            i += 1;
+            continue;
+        }
+
+        if trimmed.contains('|') && i + 1 < lines.len() && is_table_separator(lines[i + 1]) {
+            flush_paragraph(&mut paragraph, tight, output);
+            i = render_table(lines, i, output);
+            continue;
+        }
+
+        if depth < MAX_NESTING && trimmed.starts_with('>') {
+            flush_paragraph(&mut paragraph, tight, output);
+            let mut quoted = Vec::new();
+            while i < lines.len() {
+                let quote = lines[i].trim_start();
+                match quote.strip_prefix('>') {
+                    Some(rest) => quoted.push(rest.strip_prefix(' ').unwrap_or(rest)),
+                    None => break,
+                }
+                i += 1;
+            }
+            output.push_str("<blockquote>\n");
+            render_blocks(&quoted, false, depth + 1, output);
+            output.push_str("</blockquote>\n");
+            continue;
+        }
+
+        if let Some(item) = list_item(trimmed).filter(|_| depth < MAX_NESTING) {
+            flush_paragraph(&mut paragraph, tight, output);
+            i = render_list(lines, i, item, depth, output);
+            continue;
+        }
+
+        paragraph.push(line);
+        i += 1;
+    }
+
+    flush_paragraph(&mut paragraph, tight, output);
+}
+
+fn flush_paragraph(paragraph: &mut Vec<&str>, tight: bool, output: &mut String) {
+    if paragraph.is_empty() {
+        return;
+    }
+
+    let mut html = String::new();
+    for (n, line) in paragraph.iter().enumerate() {
+        if n > 0 {
+            html.push('\n');
+        }
+
+        // Two trailing spaces or a backslash make a hard line break
+        let hard_break = n + 1 < paragraph.len() && (line.ends_with("  ") || line.ends_with('\\'));
+        let line = line.trim();
+        let line = if hard_break { line.trim_end_matches('\\') } else { line };
+        html.push_str(&inline(line));
+        if hard_break {
+            html.push_str("<br>");
+        }
+    }
+
+    if tight {
+        output.push_str(&html);
+        output.push('\n');
+    } else {
+        output.push_str(&format!("<p>{}</p>\n", html));
+    }
+    paragraph.clear();
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) && (line.len() == level || line[level..].starts_with([' ', '\t'])) {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ["*", "-", "_"]
+            .iter()
+            .any(|mark| marks.chars().all(|c| c.to_string() == *mark))
+}
+
+fn is_setext_underline(line: &str) -> bool {
+    let line = line.trim_end();
+    !line.is_empty() && (line.chars().all(|c| c == '=') || line.chars().all(|c| c == '-'))
+}
+
+fn render_fence(lines: &[&str], start: usize, output: &mut String) -> usize {
+    let opening = lines[start].trim_start();
+    let fence_char = opening.chars().next().unwrap_or('`');
+    let fence_length = opening.chars().take_while(|c| *c == fence_char).count();
+    let language: String = opening[fence_length..]
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '+' || *c == '#')
+        .collect();
+
+    let mut code = Vec::new();
+    let mut i = start + 1;
+    while i < lines.len() {
+        let line = lines[i].trim_start();
+        if line.starts_with(&fence_char.to_string().repeat(fence_length)) && line.trim_matches(fence_char).trim().is_empty() {
+            i += 1;
+            break;
+        }
+        code.push(lines[i]);
+        i += 1;
+    }
+
+    if language.is_empty() {
+        output.push_str("<pre><code>");
+    } else {
+        output.push_str(&format!("<pre><code class=\"language-{}\">", escape(&language)));
+    }
+    output.push_str(&escape(&code.join("\n")));
+    output.push_str("</code></pre>\n");
+
+    i
+}
+
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell)),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell);
+
+    cells.into_iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+fn is_table_separator(line: &str) -> bool {
+    if !line.contains('-') {
+        return false;
+    }
+
+    split_row(line).iter().all(|cell| {
+        let cell = cell.trim_start_matches(':').trim_end_matches(':');
+        !cell.is_empty() && cell.chars().all(|c| c == '-')
+    })
+}
+
+fn render_table(lines: &[&str], start: usize, output: &mut String) -> usize {
+    let header = split_row(lines[start]);
+    let alignments: Vec<&str> = split_row(lines[start + 1])
+        .iter()
+        .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+            (true, true) => " style=\"text-align: center\"",
+            (false, true) => " style=\"text-align: right\"",
+            (true, false) => " style=\"text-align: left\"",
+            (false, false) => "",
+        })
+        .collect();
+    let alignment = |n: usize| alignments.get(n).copied().unwrap_or("");
+
+    output.push_str("<table class=\"table table-dark table-sm\">\n<thead>\n<tr>");
+    for (n, cell) in header.iter().enumerate() {
+        output.push_str(&format!("<th{}>{}</th>", alignment(n), inline(cell)));
+    }
+    output.push_str("</tr>\n</thead>\n<tbody>\n");
+
+    let mut i = start + 2;
+    while i < lines.len() && !lines[i].trim().is_empty() && lines[i].contains('|') {
+        let row = split_row(lines[i]);
+        output.push_str("<tr>");
+        for n in 0..header.len() {
+            let cell = row.get(n).map(String::as_str).unwrap_or("");
+            output.push_str(&format!("<td{}>{}</td>", alignment(n), inline(cell)));
+        }
+        output.push_str("</tr>\n");
+        i += 1;
+    }
+
+    output.push_str("</tbody>\n</table>\n");
+    i
+}
+
+struct ListItem {
+    ordered: bool,
+    number: u32,
+    width: usize,
+}
+
+fn list_item(line: &str) -> Option<ListItem> {
+    if is_rule(line) {
+        return None;
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if line.starts_with(marker) {
+            return Some(ListItem { ordered: false, number: 0, width: 2 });
+        }
+    }
+
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if (1..=9).contains(&digits) {
+        let rest = &line[digits..];
+        if rest.starts_with(". ") || rest.starts_with(") ") {
+            let number = line[..digits].parse().unwrap_or(1);
+            return Some(ListItem { ordered: true, number, width: digits + 2 });
+        }
+    }
+
+    None
+}
+
+// Only spaces and tabs count towards indentation, so it's always safe to slice it off
+fn indentation(line: &str) -> (&str, usize) {
+    let trimmed = line.trim_start_matches([' ', '\t']);
+    (trimmed, line.len() - trimmed.len())
+}
+
+fn render_list(lines: &[&str], start: usize, first: ListItem, depth: usize, output: &mut String) -> usize {
+    let mut items: Vec<Vec<&str>> = Vec::new();
+    let mut loose = false;
+    let mut i = start;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let (trimmed, indent) = indentation(line);
+
+        if trimmed.trim().is_empty() {
+            // Blank lines only keep the list going if more of it follows
+            let next = (i + 1..lines.len()).find(|n| !lines[*n].trim().is_empty());
+            let continues = match next {
+                Some(next) => {
+                    let (next_trimmed, next_indent) = indentation(lines[next]);
+                    next_indent >= 2
+                        || list_item(next_trimmed).is_some_and(|item| item.ordered == first.ordered)
+                }
+                None => false,
+            };
+            if !continues {
+                break;
+            }
+
+            let next = next.unwrap_or(lines.len());
+            loose = true;
+            if let Some(item) = items.last_mut() {
+                (i..next).for_each(|_| item.push(""));
+            }
+            i = next;
+            continue;
+        }
+
+        if indent < 2 {
+            if let Some(item) = list_item(trimmed) {
+                if item.ordered != first.ordered {
+                    break;
+                }
+                items.push(vec![&trimmed[item.width..]]);
+                i += 1;
+                continue;
+            }
+        }
+
+        let item = match items.last_mut() {
+            Some(item) => item,
+            None => break,
+        };
+
+        if indent >= 2 {
+            // Continuation lines belong to the item, nested lists included
+            item.push(&line[indent.min(first.width.max(2))..]);
+        } else if item.last().is_some_and(|last| !last.is_empty())
+            && heading_level(trimmed).is_none()
+            && !trimmed.starts_with('>')
+            && !trimmed.starts_with("```")
+        {
+            // Lazy continuation of the item's paragraph
+            item.push(trimmed);
+        } else {
+            break;
+        }
+        i += 1;
+    }
+
+    if first.ordered {
+        if first.number == 1 {
+            output.push_str("<ol>\n");
+        } else {
+            output.push_str(&format!("<ol start=\"{}\">\n", first.number));
+        }
+    } else {
+        output.push_str("<ul>\n");
+    }
+
+    for item in items.iter() {
+        output.push_str("<li>");
+        render_blocks(item, !loose, depth + 1, output);
+        output.push_str("</li>\n");
+    }
+
+    output.push_str(if first.ordered { "</ol>\n" } else { "</ul>\n" });
+    i
+}
+
+fn inline(text: &str) -> String {
+    let mut inline = Inline::new(text);
+    let mut output = String::with_capacity(text.len());
+    inline.render(0, inline.chars.len(), 0, &mut output);
+    output
+}
+
+// Where the next match at or after a position is, inline markup is rendered front to back
+// so the answer stays good for a while and every character only gets looked at once
+#[derive(Clone, Copy)]
+struct Scan {
+    from: usize,
+    found: usize,
+}
+
+impl Scan {
+    fn next(&mut self, at: usize, length: usize, matches: impl Fn(usize) -> bool) -> usize {
+        if at < self.from || self.found < at {
+            self.from = at;
+            self.found = (at..length).find(|j| matches(*j)).unwrap_or(length);
+        }
+        self.found
+    }
+}
+
+struct Inline {
+    chars: Vec<char>,
+    // The ] closing every [ and the ) closing every (
+    pairs: HashMap<usize, usize>,
+    // Where backtick runs start, by how long they are
+    ticks: HashMap<usize, VecDeque<usize>>,
+    // One for each of CLOSERS, then autolinks in <> and bare ones
+    scans: [Scan; 7],
+}
+
+impl Inline {
+    fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut pairs = HashMap::new();
+        let mut ticks: HashMap<usize, VecDeque<usize>> = HashMap::new();
+        let mut brackets = Vec::new();
+        let mut parens = Vec::new();
+
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '[' => brackets.push(i),
+                '(' => parens.push(i),
+                ']' => {
+                    if let Some(open) = brackets.pop() {
+                        pairs.insert(open, i);
+                    }
+                }
+                ')' => {
+                    if let Some(open) = parens.pop() {
+                        pairs.insert(open, i);
+                    }
+                }
+                '`' => {
+                    let run = run_length(&chars, i, '`');
+                    ticks.entry(run).or_default().push_back(i);
+                    i += run;
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        Inline {
+            chars,
+            pairs,
+            ticks,
+            scans: [Scan { from: usize::MAX, found: 0 }; 7],
+        }
+    }
+
+    fn render(&mut self, start: usize, end: usize, depth: usize, output: &mut String) {
+        let mut i = start;
+
+        while i < end {
+            let c = self.chars[i];
+            match c {
+                '\\' if i + 1 < end && self.chars[i + 1].is_ascii_punctuation() => {
+                    push_escaped(output, self.chars[i + 1]);
+                    i += 2;
+                }
+                '`' => {
+                    let run = run_length(&self.chars[..end], i, '`');
+                    match self.code_span(i + run, end, run) {
+                        Some(close) => {
+                            let code: String = self.chars[i + run..close].iter().collect();
+                            output.push_str(&format!("<code>{}</code>", escape(code.trim())));
+                            i = close + run;
+                        }
+                        None => {
+                            (0..run).for_each(|_| output.push('`'));
+                            i += run;
+                        }
+                    }
+                }
+                '!' if i + 1 < end && self.chars[i + 1] == '[' => match self.link(i + 1, end, depth) {
+                    // Images would pull in external resources, so they become plain links
+                    Some((label, url, next)) => {
+                        let label: String = self.chars[label].iter().collect();
+                        push_link(output, &url, &escape(&label));
+                        i = next;
+                    }
+                    None => {
+                        output.push('!');
+                        i += 1;
+                    }
+                },
+                '[' => match self.link(i, end, depth) {
+                    Some((label, url, next)) => {
+                        let mut text = String::new();
+                        self.render(label.start, label.end, depth + 1, &mut text);
+                        push_link(output, &url, &text);
+                        i = next;
+                    }
+                    None => {
+                        output.push('[');
+                        i += 1;
+                    }
+                },
+                '<' => {
+                    let chars = &self.chars;
+                    let close = self.scans[5].next(i + 1, chars.len(), |j| chars[j].is_whitespace() || chars[j] == '>');
+                    let url: String = if close < end && chars[close] == '>' {
+                        chars[i + 1..close].iter().collect()
+                    } else {
+                        String::new()
+                    };
+                    if is_autolink(&url) {
+                        push_link(output, &url, &escape(&url));
+                        i = close + 1;
+                    } else {
+                        output.push_str("&lt;");
+                        i += 1;
+                    }
+                }
+                'h' if (i == 0 || !self.chars[i - 1].is_alphanumeric())
+                    && (starts_with(&self.chars[..end], i, "http://") || starts_with(&self.chars[..end], i, "https://")) =>
+                {
+                    let chars = &self.chars;
+                    let url_end = self.scans[6]
+                        .next(i, chars.len(), |j| chars[j].is_whitespace() || chars[j] == '<')
+                        .min(end);
+                    let mut url: String = chars[i..url_end].iter().collect();
+                    while url.ends_with(['.', ',', ';', ':', '!', '?', ')']) {
+                        url.pop();
+                    }
+
+                    if is_autolink(&url) {
+                        push_link(output, &url, &escape(&url));
+                        i += url.chars().count();
+                    } else {
+                        output.push('h');
+                        i += 1;
+                    }
+                }
+                '*' | '_' | '~' => {
+                    let run = run_length(&self.chars[..end], i, c);
+                    match self.emphasis(i, end, depth, c, run) {
+                        Some((tag, width, close)) => {
+                            output.push_str(&format!("<{}>", tag));
+                            self.render(i + width, close, depth + 1, output);
+                            output.push_str(&format!("</{}>", tag));
+                            i = close + width;
+                        }
+                        None => {
+                            (0..run).for_each(|_| push_escaped(output, c));
+                            i += run;
+                        }
+                    }
+                }
+                _ => {
+                    push_escaped(output, c);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    // The next backtick run just as long as the one that opened the code span
+    fn code_span(&mut self, from: usize, end: usize, run: usize) -> Option<usize> {
+        let starts = self.ticks.get_mut(&run)?;
+        while starts.front().is_some_and(|start| *start < from) {
+            starts.pop_front();
+        }
+        starts.front().copied().filter(|close| close + run <= end)
+    }
+
+    fn emphasis(
+        &mut self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        mark: char,
+        run: usize,
+    ) -> Option<(&'static str, usize, usize)> {
+        // Underscores inside words are just underscores
+        if depth >= MAX_NESTING || (mark == '_' && start > 0 && self.chars[start - 1].is_alphanumeric()) {
+            return None;
+        }
+
+        let candidates: &[(usize, &'static str)] = match mark {
+            '~' => &[(2, "del")],
+            _ if run >= 2 => &[(2, "strong"), (1, "em")],
+            _ => &[(1, "em")],
+        };
+
+        for (width, tag) in candidates.iter() {
+            let opens = start + width < end && !self.chars[start + width].is_whitespace();
+            if !opens {
+                continue;
+            }
+
+            let scan = CLOSERS.iter().position(|closer| *closer == (mark, *width))?;
+            let chars = &self.chars;
+            let close = self.scans[scan].next(start + width + 1, chars.len(), |j| is_closer(chars, j, mark, *width));
+            if close + width <= end {
+                return Some((tag, *width, close));
+            }
+        }
+
+        None
+    }
+
+    fn link(&self, start: usize, end: usize, depth: usize) -> Option<(Range<usize>, String, usize)> {
+        if depth >= MAX_NESTING {
+            return None;
+        }
+
+        let close = *self.pairs.get(&start)?;
+        if close + 1 >= end || self.chars[close + 1] != '(' {
+            return None;
+        }
+
+        let target_end = *self.pairs.get(&(close + 1))?;
+        if target_end >= end {
+            return None;
+        }
+
+        let target: String = self.chars[close + 2..target_end].iter().collect();
+        let url = target.split_whitespace().next().unwrap_or("");
+        let url = url.trim_start_matches('<').trim_end_matches('>');
+
+        Some((start + 1..close, url.to_string(), target_end + 1))
+    }
+}
+
+fn is_closer(chars: &[char], j: usize, mark: char, width: usize) -> bool {
+    j + width <= chars.len()
+        && chars[j..j + width].iter().all(|c| *c == mark)
+        && !chars[j - 1].is_whitespace()
+        && (mark != '_' || chars.get(j + width).is_none_or(|c| !c.is_alphanumeric()))
+}
+
+fn push_link(output: &mut String, url: &str, text: &str) {
+    if is_safe_url(url) {
+        output.push_str(&format!(
+            "<a href=\"{}\" rel=\"nofollow noopener noreferrer\" target=\"_blank\">{}</a>",
+            escape(url),
+            text
+        ));
+    } else {
+        output.push_str(text);
+    }
+}
+
+fn is_autolink(url: &str) -> bool {
+    (url.starts_with("http://") || url.starts_with("https://")) && url.len() > 8 && !url.contains(char::is_whitespace)
+}
+
+// Links may go to the web, mail or somewhere relative, never javascript: and friends
+fn is_safe_url(url: &str) -> bool {
+    let lower = url.trim().to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:") {
+        return true;
+    }
+
+    match lower.find(':') {
+        Some(colon) => lower[..colon].contains(['/', '?', '#']),
+        None => true,
+    }
+}
+
+fn run_length(chars: &[char], start: usize, c: char) -> usize {
+    chars[start..].iter().take_while(|x| **x == c).count()
+}
+
+fn starts_with(chars: &[char], start: usize, prefix: &str) -> bool {
+    prefix.chars().enumerate().all(|(n, c)| chars.get(start + n) == Some(&c))
+}
+
+fn push_escaped(output: &mut String, c: char) {
+    match c {
+        '&' => output.push_str("&amp;"),
+        '<' => output.push_str("&lt;"),
+        '>' => output.push_str("&gt;"),
+        '"' => output.push_str("&quot;"),
+        '\'' => output.push_str("&#39;"),
+        _ => output.push(c),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    text.chars().for_each(|c| push_escaped(&mut output, c));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_html;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn renders_blocks() {
+        assert_eq!(to_html("# Title\n\nSome text"), "<h1>Title</h1>\n<p>Some text</p>\n");
+        assert_eq!(to_html("Title\n==="), "<h1>Title</h1>\n");
+        assert_eq!(to_html("> quoted"), "<blockquote>\n<p>quoted</p>\n</blockquote>\n");
+        assert_eq!(to_html("---"), "<hr>\n");
+    }
+
+    #[test]
+    fn renders_fenced_code() {
+        assert_eq!(
+            to_html("```rust\nlet a = 1 < 2;\n```"),
+            "<pre><code class=\"language-rust\">let a = 1 &lt; 2;</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn renders_lists() {
+        assert_eq!(to_html("- one\n- two"), "<ul>\n<li>one\n</li>\n<li>two\n</li>\n</ul>\n");
+        assert_eq!(to_html("3. three\n4. four"), "<ol start=\"3\">\n<li>three\n</li>\n<li>four\n</li>\n</ol>\n");
+        assert_eq!(
+            to_html("- one\n  - nested"),
+            "<ul>\n<li>one\n<ul>\n<li>nested\n</li>\n</ul>\n</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn list_continuations_with_unicode_spaces() {
+        assert_eq!(
+            to_html("- item\n\u{2003}continued\n"),
+            "<ul>\n<li>item\ncontinued\n</li>\n</ul>\n"
+        );
+        assert_eq!(
+            to_html("- item\n  \u{2003}continued\n"),
+            "<ul>\n<li>item\ncontinued\n</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn renders_tables() {
+        assert_eq!(
+            to_html("| a | b |\n|:--|--:|\n| 1 | 2 |"),
+            "<table class=\"table table-dark table-sm\">\n<thead>\n<tr><th style=\"text-align: left\">a</th>\
+             <th style=\"text-align: right\">b</th></tr>\n</thead>\n<tbody>\n<tr><td style=\"text-align: left\">1</td>\
+             <td style=\"text-align: right\">2</td></tr>\n</tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn renders_emphasis() {
+        assert_eq!(to_html("*em* **strong** ~~del~~"), "<p><em>em</em> <strong>strong</strong> <del>del</del></p>\n");
+        assert_eq!(to_html("**bold *em* text**"), "<p><strong>bold <em>em</em> text</strong></p>\n");
+        assert_eq!(to_html("snake_case_name"), "<p>snake_case_name</p>\n");
+        assert_eq!(to_html("* not a list*"), "<ul>\n<li>not a list*\n</li>\n</ul>\n");
+        assert_eq!(to_html("a * b * c"), "<p>a * b * c</p>\n");
+    }
+
+    #[test]
+    fn renders_code_spans() {
+        assert_eq!(to_html("``a ` b`` `c`"), "<p><code>a ` b</code> <code>c</code></p>\n");
+        assert_eq!(to_html("`unclosed"), "<p>`unclosed</p>\n");
+    }
+
+    #[test]
+    fn renders_links() {
+        assert_eq!(
+            to_html("[a *b*](https://example.com)"),
+            "<p><a href=\"https://example.com\" rel=\"nofollow noopener noreferrer\" target=\"_blank\">a <em>b</em></a></p>\n"
+        );
+        assert_eq!(
+            to_html("![alt](https://example.com/a.png)"),
+            "<p><a href=\"https://example.com/a.png\" rel=\"nofollow noopener noreferrer\" target=\"_blank\">alt</a></p>\n"
+        );
+        assert_eq!(
+            to_html("<https://example.com> and https://example.org."),
+            "<p><a href=\"https://example.com\" rel=\"nofollow noopener noreferrer\" target=\"_blank\">https://example.com</a> \
+             and <a href=\"https://example.org\" rel=\"nofollow noopener noreferrer\" target=\"_blank\">https://example.org</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn drops_unsafe_links() {
+        assert_eq!(to_html("[click](javascript:alert(1))"), "<p>click</p>\n");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(to_html("<script>alert('hi')</script>"), "<p>&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt;</p>\n");
+    }
+
+    #[test]
+    fn deep_nesting_stays_text() {
+        let quotes = ">".repeat(100_000);
+        assert!(to_html(&quotes).contains("&gt;"));
+
+        let brackets = format!("{}a{}", "[".repeat(100_000), "](x)".repeat(100_000));
+        assert!(to_html(&brackets).contains("[["));
+    }
+
+    #[test]
+    fn unclosed_markup_stays_fast() {
+        let inputs = [
+            "*a ".repeat(80_000),
+            "**a ".repeat(60_000),
+            "_a ".repeat(80_000),
+            "~~a ".repeat(60_000),
+            "[a](".repeat(60_000),
+            "`a ".repeat(80_000),
+            "<a ".repeat(80_000),
+            "http(".repeat(60_000),
+            format!("- a{}b", "\n".repeat(80_000)),
+        ];
+        for input in inputs {
+            let started = Instant::now();
+            to_html(&input);
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use pbkdf2::Pbkdf2;
+use pbkdf2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use rand::RngExt;
+use scc::HashMap;
+use std::sync::OnceLock;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::error;
+
+const MAX_ATTEMPTS: u8 = 5; // wrong passwords allowed per paste per client
+const ATTEMPTS_WINDOW: Duration = Duration::from_secs(15 * 60); // before they get another go
+
+struct Attempts {
+    count: u8,
+    since: Instant,
+}
+
+static UNLOCK_ATTEMPTS: OnceLock<HashMap<(String, String), Attempts>> = OnceLock::new();
+fn unlock_attempts() -> &'static HashMap<(String, String), Attempts> {
+    UNLOCK_ATTEMPTS.get_or_init(HashMap::new)
+}
+
+// Hashing is deliberately slow, keep it off the async workers
+pub async fn hash(password: &str) -> Option<String> {
+    let password = password.to_string();
+    let salt: [u8; 16] = rand::rng().random();
+    let hashed = tokio::task::spawn_blocking(move || {
+        let salt = SaltString::encode_b64(&salt)?;
+        Pbkdf2
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await;
+
+    match hashed {
+        Ok(Ok(hash)) => Some(hash),
+        Ok(Err(err)) => {
+            error!("Failed to hash password: {}", err);
+            None
+        }
+        Err(err) => {
+            error!("Password hashing task failed: {}", err);
+            None
+        }
+    }
+}
+
+pub async fn verify(password: &str, password_hash: &str) -> bool {
+    let password = password.to_string();
+    let password_hash = password_hash.to_string();
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&password_hash)
+            .and_then(|hash| Pbkdf2.verify_password(password.as_bytes(), &hash))
+            .is_ok()
+    })
+    .await
+    .unwrap_or(false)
+}
+
+// Whether this client still gets to guess at this paste's password
+pub async fn can_attempt(paste_id: &str, client: &str) -> bool {
+    unlock_attempts()
+        .read_async(&(paste_id.to_string(), client.to_string()), |_, attempts| {
+            attempts.count < MAX_ATTEMPTS || attempts.since.elapsed() >= ATTEMPTS_WINDOW
+        })
+        .await
+        .unwrap_or(true)
+}
+
+pub async fn record_failure(paste_id: &str, client: &str) {
+    unlock_attempts()
+        .entry_async((paste_id.to_string(), client.to_string())).await
+        .and_modify(|attempts| {
+            if attempts.since.elapsed() >= ATTEMPTS_WINDOW {
+                attempts.count = 0;
+                attempts.since = Instant::now();
+            }
+            attempts.count = attempts.count.saturating_add(1);
+        })
+        .or_insert(Attempts {
+            count: 1,
+            since: Instant::now(),
+        });
+}
+
+pub async fn prune_attempts() {
+    loop {
+        sleep(ATTEMPTS_WINDOW).await;
+        unlock_attempts()
+            .retain_async(|_, attempts| attempts.since.elapsed() < ATTEMPTS_WINDOW)
+            .await;
+    }
+}
@@ -1,55 +1,140 @@
+use crate::ansi;
 use crate::cloudflare;
+use crate::compression::Encoding;
+use crate::config::QuotaTier;
 use crate::errors::PastebinError;
 use crate::forms;
-use crate::forms::ValidDestination;
+use crate::forms::{ValidDestination, ValidExpiry};
 use crate::gdrive;
+use crate::highlight;
+use crate::highlight::Language;
+use crate::markdown;
+use crate::password;
 use crate::runtime;
 use crate::s3;
+use crate::sanitize;
 use crate::utils;
 use bigdecimal::BigDecimal;
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 use num_traits::FromPrimitive;
 use num_traits::ToPrimitive;
 use rand::RngExt;
 use scc::HashMap;
 use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgPool;
+use sha2::{Digest, Sha256};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgExecutor, PgPool, PgTypeInfo, PgValueRef, Postgres};
 use sqlx::types::chrono::DateTime;
 use sqlx::Error::RowNotFound;
-use sqlx::{query, query_as, FromRow};
+use sqlx::{query, query_as, query_scalar, FromRow, Transaction};
 use std::sync::OnceLock;
 use tokio::time::{sleep, Duration};
-use tracing::error;
+use tracing::{error, info, warn};
 
 static COUNTER: OnceLock<HashMap<String, i64>> = OnceLock::new();
 fn counter() -> &'static HashMap<String, i64> {
     COUNTER.get_or_init(HashMap::new)
 }
 
-/// This function can generate approximately 318 quadrillion unique paste IDs!
-fn generate_paste_id() -> String {
-    let url_safe_characters = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_.~";
-    let alphanumeric = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+// The paste_id column is varchar(64)
+pub const MAX_PASTE_ID_LENGTH: usize = 64;
+
+// These would shadow our own routes under /pastebin/
+const RESERVED_SLUGS: [&str; 9] = [
+    "about", "account", "api", "auth", "create", "diff", "pastes", "search", "uploads",
+];
+
+// Bigger ANSI logs aren't rendered on our side for every view, the export is there for those
+pub const MAX_RENDERED_LOG: usize = 1024 * 1024;
+
+// How many times we roll a new paste ID before giving up on a collision streak
+const PASTE_ID_ATTEMPTS: usize = 5;
+
+// Plenty for a raid debrief
+const MAX_BUNDLE_FILES: usize = 10;
+
+// Screenshots per paste, each one is held to the same 2MB as paste content
+const MAX_ATTACHMENTS: i64 = 10;
+const MAX_ATTACHMENT_SIZE: usize = 2 * 1024 * 1024;
+
+// Images are told apart by their magic bytes, whatever they claim to be called
+fn image_type(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(("image/png", "png"))
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some(("image/jpeg", "jpg"))
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some(("image/webp", "webp"))
+    } else {
+        None
+    }
+}
+
+/// With the default alphabet and length of 8 this can generate approximately 318 quadrillion unique paste IDs!
+fn generate_paste_id(url_safe_characters: &str, length: usize) -> String {
+    let url_safe_characters: Vec<char> = url_safe_characters.chars().collect();
+    let alphanumeric: Vec<char> = url_safe_characters
+        .iter()
+        .copied()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
     let mut rng = rand::rng();
     let mut paste_id = String::new();
     let mut index: usize;
 
     // Ensure we don't end up with a weird character in the beginning
     index = rng.random_range(0..alphanumeric.len());
-    paste_id.push(alphanumeric.chars().nth(index).unwrap());
+    paste_id.push(alphanumeric[index]);
 
-    for _ in 0..6 {
+    for _ in 0..length - 2 {
         index = rng.random_range(0..url_safe_characters.len());
-        paste_id.push(url_safe_characters.chars().nth(index).unwrap());
+        paste_id.push(url_safe_characters[index]);
     }
 
     // Ensure we don't end up with a weird character in the end
     index = rng.random_range(0..alphanumeric.len());
-    paste_id.push(alphanumeric.chars().nth(index).unwrap());
+    paste_id.push(alphanumeric[index]);
 
     paste_id
 }
 
+// Vanity paste IDs follow the same rules as the ones we generate
+// Paste IDs that would shadow one of our own routes
+fn is_reserved(paste_id: &str) -> bool {
+    RESERVED_SLUGS.contains(&paste_id.to_lowercase().as_str())
+}
+
+fn validate_slug(slug: &str, alphabet: &str) -> Result<(), PastebinError> {
+    if slug.len() < 3 || slug.len() > MAX_PASTE_ID_LENGTH {
+        return Err(PastebinError::Validation(format!(
+            "Paste IDs must be between 3 and {} characters long!",
+            MAX_PASTE_ID_LENGTH
+        )));
+    }
+
+    if !slug.chars().all(|c| alphabet.contains(c)) {
+        return Err(PastebinError::Validation(format!(
+            "Paste IDs may only contain these characters: {}",
+            alphabet
+        )));
+    }
+
+    // Ensure we don't end up with a weird character in the beginning or the end
+    let first = slug.chars().next().unwrap_or_default();
+    let last = slug.chars().last().unwrap_or_default();
+    if !first.is_ascii_alphanumeric() || !last.is_ascii_alphanumeric() {
+        return Err(PastebinError::Validation(
+            "Paste IDs must start and end with a letter or a number!".to_string(),
+        ));
+    }
+
+    if is_reserved(slug) {
+        return Err(PastebinError::Validation("That paste ID is reserved!".to_string()));
+    }
+
+    Ok(())
+}
+
 pub fn fix_tags(tags: &Option<String>) -> Vec<String> {
     // Limit tags to 15 of no more than 15 alphanumeric each
     let tags: Vec<String> = tags
@@ -85,25 +170,363 @@ pub async fn new_paste(
         }
     }
 
-    let mut paste = Paste::new(form, score, user_id, session_id)?;
-    paste.save(state, &form.content, &form.destination, gdrive_token).await
+    let mut paste = Paste::new(state, form, score, user_id, session_id).await?;
+    Paste::validate_content(&paste.format, &form.content)?;
+    let files = Paste::prepare_files(form).await?;
+
+    // Turn away anyone already over their quota before the content gets crunched, save checks again for real
+    check_quota(state, &paste.user_id, true).await?;
+    paste.save(state, &form.content, &form.destination, gdrive_token, &files).await
+}
+
+// What a signed in user keeps with us, Drive pastes live in their own Drive and only count as pastes
+pub struct Usage {
+    pub pastes: i64,
+    pub bytes: i64,
+}
+
+pub async fn get_usage(db: impl PgExecutor<'_>, user_id: &str) -> Result<Usage, PastebinError> {
+    // Edited pastes have all of their content in paste_revisions, the first file of a bundle is the paste itself
+    let usage = query_as!(
+        Usage,
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM pastebin WHERE user_id = $1) AS "pastes!",
+            (
+                SELECT COALESCE(SUM(s3_content_length), 0) FROM pastebin
+                WHERE user_id = $1 AND revision = 0 AND gdriveid IS NULL
+            ) + (
+                SELECT COALESCE(SUM(r.s3_content_length), 0) FROM paste_revisions r
+                JOIN pastebin p ON p.paste_id = r.paste_id
+                WHERE p.user_id = $1
+            ) + (
+                SELECT COALESCE(SUM(f.s3_content_length), 0) FROM paste_files f
+                JOIN pastebin p ON p.paste_id = f.paste_id
+                WHERE p.user_id = $1 AND f.position > 0
+            ) + (
+                SELECT COALESCE(SUM(a.s3_content_length), 0) FROM paste_attachments a
+                JOIN pastebin p ON p.paste_id = a.paste_id
+                WHERE p.user_id = $1
+            ) AS "bytes!"
+        "#,
+        user_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(usage)
+}
+
+/// How many bytes a user has left to store under their quota, None if there's no limit
+pub async fn check_quota(
+    state: &runtime::AppState,
+    user_id: &Option<String>,
+    new_paste: bool,
+) -> Result<Option<i64>, PastebinError> {
+    // Anonymous pastes are held back by the bot check instead
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Ok(None),
+    };
+
+    let (_, tier) = state.config.quotas.tier(user_id);
+    if tier.max_bytes.is_none() && tier.max_pastes.is_none() {
+        return Ok(None);
+    }
+
+    let usage = get_usage(&state.db, user_id).await?;
+    room_left(tier, usage, new_paste)
+}
+
+/// Same as check_quota, but the user's quota stays locked until the transaction is over,
+/// so concurrent requests can't each squeeze into the same room
+pub async fn lock_quota(
+    state: &runtime::AppState,
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: &Option<String>,
+    new_paste: bool,
+) -> Result<Option<i64>, PastebinError> {
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Ok(None),
+    };
+
+    let (_, tier) = state.config.quotas.tier(user_id);
+    if tier.max_bytes.is_none() && tier.max_pastes.is_none() {
+        return Ok(None);
+    }
+
+    query!("SELECT pg_advisory_xact_lock(hashtext($1))", user_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    let usage = get_usage(&mut **transaction, user_id).await?;
+    room_left(tier, usage, new_paste)
+}
+
+fn room_left(tier: &QuotaTier, usage: Usage, new_paste: bool) -> Result<Option<i64>, PastebinError> {
+    if let Some(max_pastes) = tier.max_pastes.filter(|_| new_paste) {
+        if usage.pastes >= max_pastes {
+            return Err(PastebinError::TooMany(format!(
+                "You've reached your limit of {} pastes! Delete some to make room",
+                max_pastes
+            )));
+        }
+    }
+
+    match tier.max_bytes {
+        Some(max_bytes) if usage.bytes >= max_bytes => Err(over_quota()),
+        Some(max_bytes) => Ok(Some(max_bytes - usage.bytes)),
+        None => Ok(None),
+    }
+}
+
+pub fn over_quota() -> PastebinError {
+    PastebinError::TooBig(
+        "That's more than you have left of your storage quota! Delete some pastes to make room".to_string(),
+    )
+}
+
+// Objects that made it to S3 for something that never made it to the database
+async fn discard_objects(state: &runtime::AppState, s3_keys: &[String]) {
+    for s3_key in s3_keys {
+        if let Err(err) = s3::delete(state, s3_key, false).await {
+            error!("Failed to delete {} from S3: {}", s3_key, err);
+        }
+    }
 }
 
-#[derive(Clone, Deserialize, Serialize)]
-#[serde(from = "String")]
-#[serde(untagged)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(into = "String")]
 pub enum PasteFormat {
-    Ansi(String),
-    Html(String),
-    Text(String),
+    Text,
+    Html,
+    Ansi,
+    Markdown,
+    Code(Option<Language>), // Code pastes carry their language along, as in code:lua
+    Encrypted,
+}
+
+// Everything we need to know about each format, in one place
+pub struct FormatSpec {
+    pub format: PasteFormat,
+    pub key: &'static str,
+    pub ext: &'static str,
+    pub content_type: &'static str,
+    pub render: Option<fn(&str, Option<Language>) -> String>, // Server side rendering to HTML
+    pub renders_server_side: bool, // Pages show our rendering rather than leaving it to the browser
+    pub preformatted: bool,        // Rendered as lines of text rather than as a document
+    pub is_terminal: bool,         // Carries ANSI escape codes
+    pub is_encrypted: bool,        // Only ever readable by whoever has the key
+}
+
+pub const FORMATS: [FormatSpec; 6] = [
+    FormatSpec {
+        format: PasteFormat::Text,
+        key: "plain",
+        ext: "txt",
+        content_type: "text/plain",
+        render: Some(|content, _| ansi::escape(content)),
+        renders_server_side: false,
+        preformatted: true,
+        is_terminal: false,
+        is_encrypted: false,
+    },
+    FormatSpec {
+        format: PasteFormat::Html,
+        key: "html",
+        ext: "html",
+        content_type: "text/html",
+        render: None,
+        renders_server_side: false,
+        preformatted: false,
+        is_terminal: false,
+        is_encrypted: false,
+    },
+    FormatSpec {
+        format: PasteFormat::Ansi,
+        key: "log",
+        ext: "log",
+        content_type: "text/plain",
+        render: Some(|content, _| ansi::to_html(content)),
+        renders_server_side: false,
+        preformatted: true,
+        is_terminal: true,
+        is_encrypted: false,
+    },
+    FormatSpec {
+        format: PasteFormat::Markdown,
+        key: "md",
+        ext: "md",
+        content_type: "text/markdown",
+        render: Some(|content, _| markdown::to_html(content)),
+        renders_server_side: true,
+        preformatted: false,
+        is_terminal: false,
+        is_encrypted: false,
+    },
+    FormatSpec {
+        format: PasteFormat::Code(None),
+        key: "code",
+        ext: "txt",
+        content_type: "text/plain",
+        render: Some(highlight::to_html),
+        renders_server_side: true,
+        preformatted: true,
+        is_terminal: false,
+        is_encrypted: false,
+    },
+    FormatSpec {
+        format: PasteFormat::Encrypted,
+        key: "enc",
+        ext: "enc",
+        content_type: "application/octet-stream",
+        render: None,
+        renders_server_side: false,
+        preformatted: true,
+        is_terminal: false,
+        is_encrypted: true,
+    },
+];
+
+fn unknown_language() -> PastebinError {
+    let supported: Vec<&str> = highlight::LANGUAGES.iter().map(|language| language.key()).collect();
+    PastebinError::Validation(format!(
+        "We can't highlight that language! Try one of {}",
+        supported.join(", ")
+    ))
+}
+
+impl TryFrom<String> for PasteFormat {
+    type Error = PastebinError;
+
+    fn try_from(format: String) -> Result<Self, Self::Error> {
+        let (key, language) = match format.split_once(':') {
+            Some((key, language)) => (key, Some(language)),
+            None => (format.as_str(), None),
+        };
+
+        let spec = FORMATS.iter().find(|spec| spec.key == key).ok_or_else(|| {
+            let supported: Vec<&str> = FORMATS.iter().map(|spec| spec.key).collect();
+            PastebinError::Validation(format!("Unknown paste format! Try one of {}", supported.join(", ")))
+        })?;
+
+        match (spec.format, language) {
+            (PasteFormat::Code(_), Some(language)) => match Language::from_hint(language) {
+                Some(language) => Ok(PasteFormat::Code(Some(language))),
+                None => Err(unknown_language()),
+            },
+            (_, Some(_)) => Err(PastebinError::Validation(format!(
+                "The {} format doesn't take a language!",
+                spec.key
+            ))),
+            (format, None) => Ok(format),
+        }
+    }
+}
+
+impl From<PasteFormat> for String {
+    fn from(format: PasteFormat) -> Self {
+        format.key()
+    }
+}
+
+// Rows come back through TryFrom as well so a bad format never makes it past the database
+impl sqlx::Type<Postgres> for PasteFormat {
+    fn type_info() -> PgTypeInfo {
+        <String as sqlx::Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as sqlx::Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for PasteFormat {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let format = <String as sqlx::Decode<Postgres>>::decode(value)?;
+        Ok(PasteFormat::try_from(format)?)
+    }
+}
+
+impl PasteFormat {
+    pub fn spec(&self) -> &'static FormatSpec {
+        let discriminant = std::mem::discriminant(self);
+        FORMATS
+            .iter()
+            .find(|spec| std::mem::discriminant(&spec.format) == discriminant)
+            .expect("every format is in FORMATS")
+    }
+
+    // What we store in the database and hand out to clients
+    pub fn key(&self) -> String {
+        match self {
+            PasteFormat::Code(Some(language)) => format!("{}:{}", self.spec().key, language.key()),
+            _ => self.spec().key.to_string(),
+        }
+    }
+
+    pub fn language(&self) -> Option<Language> {
+        match self {
+            PasteFormat::Code(language) => *language,
+            _ => None,
+        }
+    }
+
+    pub fn ext(&self) -> &'static str {
+        match self.language() {
+            Some(language) => language.ext(),
+            None => self.spec().ext,
+        }
+    }
+
+    pub fn renders_server_side(&self) -> bool {
+        self.spec().renders_server_side
+    }
+
+    pub fn is_preformatted(&self) -> bool {
+        self.spec().preformatted
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.spec().is_terminal
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.spec().is_encrypted
+    }
+
+    // Anything we can render ourselves can be exported as a standalone page
+    pub fn can_export(&self) -> bool {
+        self.spec().render.is_some()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteVisibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
 }
 
-impl From<String> for PasteFormat {
-    fn from(format: String) -> Self {
-        match format.as_str() {
-            "log" => PasteFormat::Ansi(format),
-            "html" => PasteFormat::Html(format),
-            _ => PasteFormat::Text(format),
+impl From<String> for PasteVisibility {
+    fn from(visibility: String) -> Self {
+        match visibility.as_str() {
+            "unlisted" => PasteVisibility::Unlisted,
+            "private" => PasteVisibility::Private,
+            _ => PasteVisibility::Public,
+        }
+    }
+}
+
+impl PasteVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PasteVisibility::Public => "public",
+            PasteVisibility::Unlisted => "unlisted",
+            PasteVisibility::Private => "private",
         }
     }
 }
@@ -120,9 +543,70 @@ pub struct Paste {
     pub gdriveid: Option<String>, // Google Drive object ID
     pub gdrivedl: Option<String>, // Google Drive download URL
     pub s3_key: String,
+    pub content_encoding: Encoding,
     pub rcscore: BigDecimal, // Recaptcha score
     pub views: i64,
     pub last_seen: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    pub visibility: PasteVisibility,
+    pub password_hash: Option<String>,
+    pub revision: i32,
+    pub forked_from: Option<String>,
+    pub sanitize_report: Option<Vec<String>>,
+}
+
+#[derive(FromRow)]
+pub struct PasteFork {
+    pub paste_id: String,
+    pub title: Option<String>,
+    pub date: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct PasteRevision {
+    pub revision: i32,
+    pub s3_key: String,
+    pub content_encoding: Encoding,
+    pub date: DateTime<Utc>,
+}
+
+// Bundles keep several named files under the one paste ID, the first one is the paste itself
+#[derive(FromRow)]
+pub struct PasteFile {
+    pub position: i32,
+    pub name: String,
+    pub format: PasteFormat,
+    pub s3_key: String,
+    pub content_encoding: Encoding,
+}
+
+impl PasteFile {
+    pub async fn render_html(&self, content: String) -> Option<String> {
+        let render = self.format.spec().render?;
+        let language = self.format.language();
+        match tokio::task::spawn_blocking(move || render(&content, language)).await {
+            Ok(rendered) => Some(rendered),
+            Err(err) => {
+                error!("Rendering task failed: {}", err);
+                None
+            }
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct PasteAttachment {
+    pub position: i32,
+    pub content_type: String,
+    pub s3_key: String,
+}
+
+// A bundle file that's been checked over and is ready to be saved
+struct BundleFile {
+    name: String,
+    format: PasteFormat,
+    content: String,
 }
 
 // Used for DELETE /pastes/:paste_id
@@ -133,12 +617,42 @@ struct DeletePaste {
 
 #[derive(FromRow, Serialize)]
 pub struct SearchPaste {
+    pub paste_id: String,
+    pub title: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub format: PasteFormat,
+    pub language: Option<String>,
+    pub date: DateTime<Utc>,
+    pub views: i64,
+}
+
+// A bundle file waiting for its paste to be uploaded first
+struct FileUpload {
+    s3_key: String,
+    s3_content: Vec<u8>,
+    content_encoding: Encoding,
+    content_type: &'static str,
+    filename: String,
+}
+
+// An edited paste's new content, waiting to be uploaded once the edit is good to go
+struct NewRevision {
+    number: i32,
+    s3_key: String,
+    s3_content: Vec<u8>,
+    content_encoding: Encoding,
+}
+
+// A user's own pastes as the API lists them, unlisted and private ones included
+#[derive(FromRow, Serialize)]
+pub struct UserPaste {
     pub paste_id: String,
     pub title: Option<String>,
     pub tags: Option<Vec<String>>,
     pub format: PasteFormat,
     pub date: DateTime<Utc>,
     pub views: i64,
+    pub visibility: PasteVisibility,
 }
 
 impl Paste {
@@ -165,28 +679,177 @@ impl Paste {
         (title, unique_tags)
     }
 
-    fn new(
+    fn validate_content(format: &PasteFormat, content: &str) -> Result<(), PastebinError> {
+        if content.is_empty() {
+            return Err(PastebinError::Validation("Content is empty!".to_string()));
+        }
+
+        // We never see the plaintext of encrypted pastes, but it should at least look like ciphertext
+        if let PasteFormat::Encrypted = format {
+            if !content.trim().chars().all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c)) {
+                return Err(PastebinError::Validation(
+                    "Encrypted content doesn't look like ciphertext!".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // HTML pastes only keep what our allow-list lets through
+    async fn sanitize_content(
+        format: &PasteFormat,
+        content: &str,
+    ) -> Result<(String, Option<Vec<String>>), PastebinError> {
+        match format {
+            PasteFormat::Html => {
+                // Big pastes take a while to go through, keep it off the async workers
+                let content = content.to_string();
+                let (content, report) = match tokio::task::spawn_blocking(move || sanitize::html(&content)).await {
+                    Ok(sanitized) => sanitized,
+                    Err(err) => {
+                        error!("Sanitizing task failed: {}", err);
+                        return Err(PastebinError::Internal(err.to_string()));
+                    }
+                };
+                if content.trim().is_empty() {
+                    return Err(PastebinError::Validation(
+                        "There's nothing left of this paste once it's cleaned up!".to_string(),
+                    ));
+                }
+                Ok((content, Some(report)))
+            }
+            _ => Ok((content.to_string(), None)),
+        }
+    }
+
+    // Code pastes get their language from the hint if there's one or we take a guess
+    fn resolve_format(format: &str, language: Option<&str>, content: &str) -> Result<PasteFormat, PastebinError> {
+        let format = PasteFormat::try_from(format.to_string())?;
+        if !matches!(format, PasteFormat::Code(_)) {
+            return Ok(format);
+        }
+
+        let language = match language.map(str::trim).filter(|hint| !hint.is_empty()) {
+            Some(hint) => Some(Language::from_hint(hint).ok_or_else(unknown_language)?),
+            None => format.language().or_else(|| highlight::detect(content)),
+        };
+
+        Ok(PasteFormat::Code(language))
+    }
+
+    async fn prepare_files(form: &forms::PasteForm) -> Result<Vec<BundleFile>, PastebinError> {
+        if form.files.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if form.files.len() > MAX_BUNDLE_FILES {
+            return Err(PastebinError::Validation(format!(
+                "Bundles can have up to {} files!",
+                MAX_BUNDLE_FILES
+            )));
+        }
+
+        if form.burn_after_reading {
+            return Err(PastebinError::Validation(
+                "Bundles can't be burnt after reading!".to_string(),
+            ));
+        }
+
+        if form.destination != ValidDestination::DataStore {
+            return Err(PastebinError::Validation(
+                "Bundles can only be saved to our datastore!".to_string(),
+            ));
+        }
+
+        let mut files: Vec<BundleFile> = Vec::new();
+        for file in form.files.iter() {
+            let name: String = file.name.trim().chars().filter(|c| !c.is_control()).take(64).collect();
+            if name.is_empty() {
+                return Err(PastebinError::Validation(
+                    "Every file in a bundle needs a name!".to_string(),
+                ));
+            }
+            if files.iter().any(|other| other.name == name) {
+                return Err(PastebinError::Validation(format!(
+                    "There's more than one file called {}!",
+                    name
+                )));
+            }
+
+            let format = Paste::resolve_format(&file.format, file.language.as_deref(), &file.content)?;
+            if format == PasteFormat::Encrypted {
+                return Err(PastebinError::Validation(
+                    "Bundles can't hold encrypted files!".to_string(),
+                ));
+            }
+
+            Paste::validate_content(&format, &file.content)?;
+            let (content, _) = Paste::sanitize_content(&format, &file.content).await?;
+            files.push(BundleFile { name, format, content });
+        }
+
+        Ok(files)
+    }
+
+    async fn new(
+        state: &runtime::AppState,
         form: &forms::PasteForm,
         score: f64,
         user_id: Option<String>,
         session_id: Option<String>,
     ) -> Result<Self, PastebinError> {
-        if form.content.is_empty() {
-            return Err(PastebinError::Validation("Content is empty!".to_string()));
+        let format = Paste::resolve_format(&form.format, form.language.as_deref(), &form.content)?;
+
+        if form.visibility == PasteVisibility::Private && user_id.is_none() {
+            return Err(PastebinError::Validation(
+                "You need to be signed in to make private pastes!".to_string(),
+            ));
         }
 
+        let password_hash = match form.password.as_deref() {
+            Some("") | None => form.password_hash.clone(),
+            Some(password) if password.chars().count() > 128 => {
+                return Err(PastebinError::Validation("Password is too long!".to_string()));
+            }
+            Some(password) => match password::hash(password).await {
+                Some(hash) => Some(hash),
+                None => return Err(PastebinError::Internal("Failed to lock paste".to_string())),
+            },
+        };
+
         let (title, unique_tags) = Paste::clean_title_tags(&form.title, &form.tags);
 
-        let format = form.format.clone();
         let rcscore = match BigDecimal::from_f64(score) {
             Some(score) => score,
             None => return Err(PastebinError::Validation("Invalid score".to_string())),
         };
 
         let now = Utc::now();
-        let paste_id = generate_paste_id(); // FIXME: Check for duplicates before using
+        let expires_at = match form.expiry {
+            ValidExpiry::Never => None,
+            ValidExpiry::Hour => Some(now + TimeDelta::hours(1)),
+            ValidExpiry::Day => Some(now + TimeDelta::days(1)),
+            ValidExpiry::Week => Some(now + TimeDelta::weeks(1)),
+            ValidExpiry::Month => Some(now + TimeDelta::days(30)),
+        };
+
+        // Signed in users get to pick their own paste_id, everyone else gets one when the paste is saved
+        let paste_id = match form.slug.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+            Some(slug) => {
+                if user_id.is_none() {
+                    return Err(PastebinError::Auth(
+                        "You need to be signed in to pick your own paste ID!".to_string(),
+                    ));
+                }
+                validate_slug(slug, &state.config.paste_id_alphabet)?;
+                slug.to_string()
+            }
+            None => String::new(),
+        };
+
         let paste = Paste {
-            paste_id: paste_id.clone(),
+            paste_id,
             user_id,
             session_id,
             title: Some(title),
@@ -196,9 +859,17 @@ impl Paste {
             gdriveid: None,
             gdrivedl: None,
             s3_key: "".to_string(),
+            content_encoding: Encoding::Identity,
             rcscore,
             views: 0,
             last_seen: now,
+            expires_at,
+            burn_after_reading: form.burn_after_reading,
+            visibility: form.visibility,
+            password_hash,
+            revision: 0,
+            forked_from: form.forked_from.clone(),
+            sanitize_report: None,
         };
 
         Ok(paste)
@@ -210,45 +881,80 @@ impl Paste {
         content: &str,
         destination: &ValidDestination,
         gdrive_token: &str,
+        files: &[BundleFile],
     ) -> Result<String, PastebinError> {
-        // Convert rust types to SQLx types
-        let tags: Option<&[String]> = self.tags.as_deref();
-
-        let format = match self.format {
-            PasteFormat::Ansi(ref ansi) => ansi,
-            PasteFormat::Html(ref html) => html,
-            PasteFormat::Text(ref text) => text,
-        };
-
         // Determine file extension for S3
-        let ext = match self.format {
-            PasteFormat::Ansi(_) => "log",
-            PasteFormat::Text(_) => "txt",
-            PasteFormat::Html(_) => "html",
-        };
+        let ext = self.get_ext();
 
         // Determine content type for S3
-        let content_type = match self.format {
-            PasteFormat::Ansi(_) => "text/plain".to_string(),
-            PasteFormat::Text(_) => "text/plain".to_string(),
-            PasteFormat::Html(_) => "text/html".to_string(),
-        };
+        let content_type = self.get_content_type();
+
+        let (content, sanitize_report) = Paste::sanitize_content(&self.format, content).await?;
+        self.sanitize_report = sanitize_report;
+        let content_hash = hex::encode(Sha256::digest(content.as_bytes()));
 
         // Crunch crunch!
-        let mut s3_content: Vec<u8> = Vec::new();
-        let content_encoding = utils::compress(content, &mut s3_content, destination).await?;
+        let (s3_content, content_encoding) = utils::compress(state, &content, destination, &self.format).await?;
+        self.content_encoding = content_encoding;
 
-        // Let's append .br to the S3 key if we're using brotli compression
-        let mut s3_key = format!("{}{}.{}", state.config.s3_prefix, self.paste_id, ext);
-        if content_encoding == "br" {
-            s3_key.push_str(".br");
-        }
+        // Shared objects are named after their content, so they don't depend on the paste_id
+        let shared = self.can_share_content(destination);
 
         let content_length = s3_content.len() as i32;
         if content_length > 2 * 1024 * 1024 && destination != &ValidDestination::GDrive {
             return Err(PastebinError::TooBig(format!("Content length is too large: {}", content_length)));
         }
 
+        // Start a DB transaction
+        let mut transaction = match state.db.begin().await {
+            Ok(transaction) => transaction,
+            Err(err) => return Err(PastebinError::Database(err)),
+        };
+
+        // Drive pastes don't take up any of our storage
+        let room = match lock_quota(state, &mut transaction, &self.user_id, true).await? {
+            Some(room) if destination == &ValidDestination::GDrive => Some(room),
+            Some(room) if content_length as i64 > room => return Err(over_quota()),
+            Some(room) => Some(room - content_length as i64),
+            None => None,
+        };
+
+        // Keep rolling paste IDs until one of them isn't taken yet, vanity ones get just the one shot
+        let vanity = !self.paste_id.is_empty();
+        let mut s3_key = String::new();
+        let mut allocated = false;
+        for _ in 0..PASTE_ID_ATTEMPTS {
+            if !vanity {
+                self.paste_id = generate_paste_id(&state.config.paste_id_alphabet, state.config.paste_id_length);
+                if is_reserved(&self.paste_id) {
+                    continue;
+                }
+            }
+            s3_key = if shared {
+                self.build_blob_key(state, &content_hash, content_encoding)
+            } else {
+                self.build_s3_key(state, 0, content_encoding)
+            };
+
+            if self.insert(&mut transaction, &s3_key, content_length, &content_hash).await? {
+                allocated = true;
+                break;
+            }
+
+            if vanity {
+                return Err(PastebinError::Validation("That paste ID is already taken!".to_string()));
+            }
+
+            warn!("Paste ID {} is already taken, rolling another one", self.paste_id);
+        }
+
+        if !allocated {
+            return Err(PastebinError::Internal(
+                "We couldn't find a free paste ID, try again!".to_string(),
+            ));
+        }
+
+        // Drive uploads are named after the paste_id so they wait until we have one
         if destination == &ValidDestination::GDrive {
             let (gdriveid, gdrivedl) = gdrive::upload(
                 gdrive_token,
@@ -260,76 +966,396 @@ impl Paste {
             )
             .await?;
 
+            query!(
+                r#"
+                UPDATE pastebin
+                SET gdriveid = $1, gdrivedl = $2
+                WHERE paste_id = $3
+                "#,
+                gdriveid,
+                gdrivedl,
+                self.paste_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
             self.gdriveid = Some(gdriveid);
             self.gdrivedl = Some(gdrivedl);
         }
 
-        // Start a DB transaction
-        let mut transaction = match state.db.begin().await {
-            Ok(transaction) => transaction,
-            Err(err) => return Err(PastebinError::Database(err)),
+        let file_uploads = if files.is_empty() {
+            Vec::new()
+        } else {
+            self.save_files(state, &mut transaction, files, &s3_key, content_length, content_encoding, room)
+                .await?
         };
 
-        query!(
-            r#"
-            INSERT INTO pastebin (paste_id, user_id, session_id, title, tags, format, date, gdriveid, gdrivedl, s3_key, s3_content_length, rcscore, views, last_seen)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
-            "#,
-            self.paste_id,
-            self.user_id,
-            self.session_id,
-            self.title,
-            tags,
-            format,
-            self.date,
-            self.gdriveid,
-            self.gdrivedl,
-            s3_key,
-            content_length,
-            self.rcscore,
-            0,
-            self.last_seen
-        )
-        .execute(&mut *transaction)
-        .await
-        .map_err(PastebinError::Database)?;
+        // Identical content that's already stored gets another reference instead of another upload
+        let mut already_stored = false;
+        if shared {
+            let refcount: i32 = query_scalar!(
+                r#"
+                INSERT INTO blobs (s3_key, content_hash, s3_content_length, refcount, date)
+                VALUES ($1, $2, $3, 1, $4)
+                ON CONFLICT (s3_key) DO UPDATE
+                SET refcount = blobs.refcount + 1
+                RETURNING refcount
+                "#,
+                s3_key,
+                content_hash,
+                content_length,
+                self.date
+            )
+            .fetch_one(&mut *transaction)
+            .await?;
+            already_stored = refcount > 1;
+        }
+
+        // Shared objects don't carry anything about the paste that happened to upload them first
+        let (title, tags, filename) = if shared {
+            (None, None, format!("{}.{}", &content_hash[..12], ext))
+        } else {
+            (self.title.clone(), self.tags.clone(), format!("{}.{}", self.paste_id, ext))
+        };
 
         match s3::upload(
             state,
             &s3_key,
             s3_content,
             &content_type,
-            &content_encoding,
-            &self.title,
-            &self.tags,
-            &format!("{}.{}", self.paste_id, ext),
-            destination == &ValidDestination::GDrive,
+            content_encoding.as_str(),
+            &title,
+            &tags,
+            &filename,
+            destination == &ValidDestination::GDrive || already_stored,
         )
         .await
         {
-            Ok(_) => match transaction.commit().await {
-                Ok(_) => Ok(self.paste_id.clone()),
-                Err(err) => {
-                    Err(PastebinError::Database(err))
-                }
-            },
+            Ok(_) => {}
             Err(err) => match transaction.rollback().await {
-                Ok(_) => Err(PastebinError::Storage(format!("Failed to upload to S3: {}", err))),
+                Ok(_) => return Err(PastebinError::Storage(format!("Failed to upload to S3: {}", err))),
                 Err(err) => {
-                    Err(PastebinError::Database(err))
+                    return Err(PastebinError::Database(err))
                 }
             },
         }
+
+        // Shared objects may be someone else's by now, only what's ours alone gets cleaned up
+        let mut uploaded = Vec::new();
+        if !shared && destination == &ValidDestination::DataStore {
+            uploaded.push(s3_key.clone());
+        }
+
+        // Bundle files only go up once everything about them checks out and the paste itself is in
+        for upload in file_uploads {
+            if let Err(err) = s3::upload(
+                state,
+                &upload.s3_key,
+                upload.s3_content,
+                upload.content_type,
+                upload.content_encoding.as_str(),
+                &self.title,
+                &self.tags,
+                &upload.filename,
+                false,
+            )
+            .await
+            {
+                discard_objects(state, &uploaded).await;
+                return Err(PastebinError::Storage(format!("Failed to upload to S3: {}", err)));
+            }
+            uploaded.push(upload.s3_key);
+        }
+
+        match transaction.commit().await {
+            Ok(_) => Ok(self.paste_id.clone()),
+            Err(err) => {
+                discard_objects(state, &uploaded).await;
+                Err(PastebinError::Database(err))
+            }
+        }
+    }
+
+    // False if the paste_id is already taken
+    async fn insert(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        s3_key: &str,
+        content_length: i32,
+        content_hash: &str,
+    ) -> Result<bool, PastebinError> {
+        // Convert rust types to SQLx types
+        let tags: Option<&[String]> = self.tags.as_deref();
+
+        // Paste IDs left behind by renamed pastes aren't up for grabs
+        let renamed = query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM paste_renames WHERE old_paste_id = $1) AS "renamed!""#,
+            self.paste_id
+        )
+        .fetch_one(&mut **transaction)
+        .await?;
+        if renamed {
+            return Ok(false);
+        }
+
+        let inserted = query!(
+            r#"
+            INSERT INTO pastebin (paste_id, user_id, session_id, title, tags, format, date, gdriveid, gdrivedl, s3_key, s3_content_length, content_encoding, content_hash, rcscore, views, last_seen, expires_at, burn_after_reading, visibility, password_hash, forked_from, sanitize_report)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22)
+            ON CONFLICT (paste_id) DO NOTHING
+            "#,
+            self.paste_id,
+            self.user_id,
+            self.session_id,
+            self.title,
+            tags,
+            self.format.key(),
+            self.date,
+            self.gdriveid,
+            self.gdrivedl,
+            s3_key,
+            content_length,
+            self.content_encoding.as_str(),
+            content_hash,
+            self.rcscore,
+            0,
+            self.last_seen,
+            self.expires_at,
+            self.burn_after_reading,
+            self.visibility.as_str(),
+            self.password_hash,
+            self.forked_from,
+            self.sanitize_report.as_deref()
+        )
+        .execute(&mut **transaction)
+        .await
+        .map_err(PastebinError::Database)?;
+
+        Ok(inserted.rows_affected() > 0)
+    }
+
+    /// Start a paste whose content arrives in chunks, it's only saved once all of it is in
+    pub async fn new_upload(
+        state: &runtime::AppState,
+        form: &forms::PasteForm,
+        user_id: Option<String>,
+        session_id: Option<String>,
+    ) -> Result<Self, PastebinError> {
+        let mut paste = Paste::new(state, form, 0.5, user_id, session_id).await?;
+
+        // Everything else needs to see all of the content at once
+        if !matches!(
+            paste.format,
+            PasteFormat::Text | PasteFormat::Ansi | PasteFormat::Markdown | PasteFormat::Code(_)
+        ) {
+            return Err(PastebinError::Validation(
+                "Only plain, log, md and code pastes can be uploaded in chunks!".to_string(),
+            ));
+        }
+
+        // There's no telling how big it gets, so it's treated as big
+        paste.content_encoding = utils::choose_encoding(state, usize::MAX, &ValidDestination::DataStore, &paste.format);
+
+        // The paste_id names the object, so it's picked now and checked again once the upload is finished
+        let vanity = !paste.paste_id.is_empty();
+        for _ in 0..PASTE_ID_ATTEMPTS {
+            if !vanity {
+                paste.paste_id = generate_paste_id(&state.config.paste_id_alphabet, state.config.paste_id_length);
+                if is_reserved(&paste.paste_id) {
+                    continue;
+                }
+            }
+
+            let taken = query_scalar!(
+                r#"
+                SELECT EXISTS(SELECT 1 FROM pastebin WHERE paste_id = $1)
+                    OR EXISTS(SELECT 1 FROM paste_renames WHERE old_paste_id = $1) AS "taken!"
+                "#,
+                paste.paste_id
+            )
+            .fetch_one(&state.db)
+            .await?;
+            if !taken {
+                return Ok(paste);
+            }
+
+            if vanity {
+                return Err(PastebinError::Validation("That paste ID is already taken!".to_string()));
+            }
+        }
+
+        Err(PastebinError::Internal(
+            "We couldn't find a free paste ID, try again!".to_string(),
+        ))
+    }
+
+    // Uploads only settle on a key once the first chunk has shown what language a code paste is in
+    pub fn assign_s3_key(&mut self, state: &runtime::AppState) {
+        self.s3_key = self.build_s3_key(state, 0, self.content_encoding);
+    }
+
+    /// Record an uploaded paste once its content is all in storage, under the key the upload staged it at
+    pub async fn save_upload(
+        &self,
+        state: &runtime::AppState,
+        staging_key: &str,
+        content_length: i32,
+        content_hash: &str,
+    ) -> Result<String, PastebinError> {
+        let mut transaction = state.db.begin().await?;
+
+        if let Some(room) = lock_quota(state, &mut transaction, &self.user_id, true).await? {
+            if content_length as i64 > room {
+                return Err(over_quota());
+            }
+        }
+
+        if !self.insert(&mut transaction, &self.s3_key, content_length, content_hash).await? {
+            return Err(PastebinError::Validation("That paste ID is already taken!".to_string()));
+        }
+
+        // Nothing goes under the paste's own key until the paste_id is ours
+        s3::copy(state, staging_key, &self.s3_key)
+            .await
+            .map_err(|err| PastebinError::Storage(format!("Failed to copy upload in S3: {}", err)))?;
+
+        if let Err(err) = transaction.commit().await {
+            discard_objects(state, std::slice::from_ref(&self.s3_key)).await;
+            return Err(PastebinError::Database(err));
+        }
+
+        Ok(self.paste_id.clone())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn save_files(
+        &self,
+        state: &runtime::AppState,
+        transaction: &mut Transaction<'_, Postgres>,
+        files: &[BundleFile],
+        s3_key: &str,
+        content_length: i32,
+        content_encoding: Encoding,
+        mut room: Option<i64>,
+    ) -> Result<Vec<FileUpload>, PastebinError> {
+        // The first file is the paste itself and is uploaded along with it
+        let mut crunched = vec![None];
+
+        // All of the files have to fit before any of them go to S3
+        for file in files.iter().skip(1) {
+            let (s3_content, content_encoding) =
+                utils::compress(state, &file.content, &ValidDestination::DataStore, &file.format).await?;
+
+            let content_length = s3_content.len();
+            if content_length > 2 * 1024 * 1024 {
+                return Err(PastebinError::TooBig(format!(
+                    "{} is too large: {}",
+                    file.name, content_length
+                )));
+            }
+
+            if let Some(room) = room.as_mut() {
+                *room -= content_length as i64;
+                if *room < 0 {
+                    return Err(over_quota());
+                }
+            }
+
+            crunched.push(Some((s3_content, content_encoding)));
+        }
+
+        let mut uploads = Vec::new();
+        for (position, (file, crunched)) in files.iter().zip(crunched).enumerate() {
+            let position = position as i32;
+
+            let (s3_key, content_length, content_encoding) = match crunched {
+                None => (s3_key.to_string(), content_length, content_encoding),
+                Some((s3_content, content_encoding)) => {
+                    let content_length = s3_content.len() as i32;
+                    let s3_key = self.build_file_key(state, position, file.format.ext(), content_encoding);
+                    uploads.push(FileUpload {
+                        s3_key: s3_key.clone(),
+                        s3_content,
+                        content_encoding,
+                        content_type: file.format.spec().content_type,
+                        filename: format!("{}.f{}.{}", self.paste_id, position, file.format.ext()),
+                    });
+
+                    (s3_key, content_length, content_encoding)
+                }
+            };
+
+            query!(
+                r#"
+                INSERT INTO paste_files (paste_id, position, name, format, s3_key, s3_content_length, content_encoding)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+                self.paste_id,
+                position,
+                file.name,
+                file.format.key(),
+                s3_key,
+                content_length,
+                content_encoding.as_str()
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+
+        Ok(uploads)
+    }
+
+    fn build_s3_key(&self, state: &runtime::AppState, revision: i32, content_encoding: Encoding) -> String {
+        // Every revision after the first one gets its own object
+        let suffix = if revision > 0 { format!(".r{}", revision) } else { String::new() };
+        self.build_object_key(state, &suffix, self.get_ext(), content_encoding)
+    }
+
+    // Drive pastes aren't ours to share, burnable and private ones must stay behind their secret keys
+    fn can_share_content(&self, destination: &ValidDestination) -> bool {
+        destination == &ValidDestination::DataStore
+            && !self.burn_after_reading
+            && self.visibility != PasteVisibility::Private
+    }
+
+    fn build_blob_key(&self, state: &runtime::AppState, content_hash: &str, content_encoding: Encoding) -> String {
+        let prefix = if self.password_hash.is_some() {
+            &state.config.s3_private_prefix
+        } else {
+            &state.config.s3_prefix
+        };
+        format!("{}blobs/{}.{}{}", prefix, content_hash, self.get_ext(), content_encoding.suffix())
+    }
+
+    // Bundle files sit right next to the paste's own object
+    fn build_file_key(&self, state: &runtime::AppState, position: i32, ext: &str, content_encoding: Encoding) -> String {
+        self.build_object_key(state, &format!(".f{}", position), ext, content_encoding)
+    }
+
+    // Locked, burnable and private pastes live outside the public prefix and are only ever served through us
+    pub fn keeps_content_private(&self) -> bool {
+        self.password_hash.is_some() || self.burn_after_reading || self.visibility == PasteVisibility::Private
+    }
+
+    fn build_object_key(&self, state: &runtime::AppState, suffix: &str, ext: &str, content_encoding: Encoding) -> String {
+        let prefix = if self.keeps_content_private() {
+            &state.config.s3_private_prefix
+        } else {
+            &state.config.s3_prefix
+        };
+
+        // The encoding is recorded alongside the key, the suffix is just for whoever browses the bucket
+        format!("{}{}{}.{}{}", prefix, self.paste_id, suffix, ext, content_encoding.suffix())
     }
 
     pub async fn get(db: &PgPool, paste_id: &str) -> Result<Paste, PastebinError> {
-        let paste_id = paste_id.chars().take(8).collect::<String>();
+        let paste_id = paste_id.chars().take(MAX_PASTE_ID_LENGTH).collect::<String>();
         let paste = match query_as!(
             Paste,
             r#"
-                SELECT paste_id, user_id, session_id, title, tags, format, date, gdriveid, gdrivedl, s3_key, rcscore, views, last_seen
+                SELECT paste_id, user_id, session_id, title, tags, format AS "format: PasteFormat", date, gdriveid, gdrivedl, s3_key, content_encoding AS "content_encoding: Encoding", rcscore, views, last_seen, expires_at, burn_after_reading, visibility, password_hash, revision, forked_from, sanitize_report
                 FROM pastebin
-                WHERE paste_id = $1
+                WHERE paste_id = $1 AND (expires_at IS NULL OR expires_at > NOW())
                 "#,
             paste_id
         )
@@ -355,7 +1381,27 @@ impl Paste {
         state: &runtime::AppState,
         title: &Option<String>,
         tags: &Option<String>,
-    ) -> Result<(), PastebinError> {
+        content: &Option<String>,
+        slug: &Option<String>,
+    ) -> Result<String, PastebinError> {
+        // A new paste ID has to pass muster before anything else gets changed
+        let slug = slug.as_deref().map(str::trim).filter(|s| !s.is_empty() && *s != self.paste_id);
+        if let Some(slug) = slug {
+            if self.user_id.is_none() {
+                return Err(PastebinError::Auth(
+                    "Only pastes made while signed in can get their own paste ID!".to_string(),
+                ));
+            }
+            validate_slug(slug, &state.config.paste_id_alphabet)?;
+        }
+
+        let mut transaction = state.db.begin().await?;
+
+        let revision = match content.as_deref().filter(|c| !c.is_empty()) {
+            Some(content) => Some(self.replace_content(state, &mut transaction, content).await?),
+            None => None,
+        };
+
         let (title, unique_tags) = Paste::clean_title_tags(title, tags);
 
         // Convert rust types to SQLx types
@@ -367,19 +1413,367 @@ impl Paste {
             SET title = $1, tags = $2
             WHERE paste_id = $3
             "#,
-            Some(title),
+            Some(title.clone()),
             tags,
             self.paste_id
         )
-        .execute(&state.db)
+        .execute(&mut *transaction)
+        .await?;
+
+        if let Some(slug) = slug {
+            self.rename(&mut transaction, slug).await?;
+        }
+
+        // The new revision is only uploaded once everything else has gone through
+        if let Some(revision) = revision {
+            let filename = format!("{}.r{}.{}", self.paste_id, revision.number, self.get_ext());
+            if let Err(err) = s3::upload(
+                state,
+                &revision.s3_key,
+                revision.s3_content,
+                &self.get_content_type(),
+                revision.content_encoding.as_str(),
+                &Some(title),
+                &Some(unique_tags),
+                &filename,
+                false,
+            )
+            .await
+            {
+                return Err(PastebinError::Storage(format!("Failed to upload to S3: {}", err)));
+            }
+
+            if let Err(err) = transaction.commit().await {
+                if let Err(err) = s3::delete(state, &revision.s3_key, false).await {
+                    error!("Failed to delete {} from S3: {}", revision.s3_key, err);
+                }
+                return Err(PastebinError::Database(err));
+            }
+
+            self.purge_raw(state).await;
+        } else {
+            transaction.commit().await?;
+        }
+
+        match slug {
+            Some(slug) => {
+                // Views counted under the old paste_id have nowhere to go now
+                counter().remove_async(&self.paste_id).await;
+                Ok(slug.to_string())
+            }
+            None => Ok(self.paste_id.clone()),
+        }
+    }
+
+    // Revisions, forks and earlier renames follow along through ON UPDATE CASCADE
+    async fn rename(&self, transaction: &mut Transaction<'_, Postgres>, slug: &str) -> Result<(), PastebinError> {
+        // A paste may take back a paste ID it had before, but not one some other paste left behind
+        query!(
+            r#"DELETE FROM paste_renames WHERE old_paste_id = $1 AND paste_id = $2"#,
+            slug,
+            self.paste_id
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        let renamed = query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM paste_renames WHERE old_paste_id = $1) AS "renamed!""#,
+            slug
+        )
+        .fetch_one(&mut **transaction)
+        .await?;
+        if renamed {
+            return Err(PastebinError::Validation("That paste ID is already taken!".to_string()));
+        }
+
+        match query!(
+            r#"
+            UPDATE pastebin
+            SET paste_id = $1
+            WHERE paste_id = $2
+            "#,
+            slug,
+            self.paste_id
+        )
+        .execute(&mut **transaction)
+        .await
+        {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+                return Err(PastebinError::Validation("That paste ID is already taken!".to_string()));
+            }
+            Err(err) => return Err(PastebinError::Database(err)),
+        }
+
+        // Hang on to the old paste ID so links to it keep working
+        query!(
+            r#"
+            INSERT INTO paste_renames (old_paste_id, paste_id, date)
+            VALUES ($1, $2, $3)
+            "#,
+            self.paste_id,
+            slug,
+            Utc::now()
+        )
+        .execute(&mut **transaction)
         .await?;
 
         Ok(())
     }
 
+    /// Where a paste went after it was given a new paste ID
+    pub async fn renamed_to(db: &PgPool, paste_id: &str) -> Result<Option<String>, PastebinError> {
+        let renamed = query_scalar!(
+            r#"SELECT paste_id FROM paste_renames WHERE old_paste_id = $1"#,
+            paste_id
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(renamed)
+    }
+
+    pub fn can_edit_content(&self) -> bool {
+        self.gdrivedl.is_none() && !self.burn_after_reading
+    }
+
+    // Raw content of public pastes is cached at the edge for a few minutes
+    async fn purge_raw(&self, state: &runtime::AppState) {
+        if self.visibility != PasteVisibility::Public || self.password_hash.is_some() {
+            return;
+        }
+
+        for domain in &state.config.allowed_domains {
+            let _ = cloudflare::queue()
+                .insert_async(format!("https://{}/pastebin/{}/raw", domain, self.paste_id))
+                .await;
+        }
+        cloudflare::purge_cache(state, false).await;
+    }
+
+    // Records a new revision in the transaction, the caller uploads it before committing
+    async fn replace_content(
+        &self,
+        state: &runtime::AppState,
+        transaction: &mut Transaction<'_, Postgres>,
+        content: &str,
+    ) -> Result<NewRevision, PastebinError> {
+        if !self.can_edit_content() {
+            return Err(PastebinError::Validation(
+                "The content of this paste can't be changed!".to_string(),
+            ));
+        }
+
+        Paste::validate_content(&self.format, content)?;
+        let (content, sanitize_report) = Paste::sanitize_content(&self.format, content).await?;
+        let content_hash = hex::encode(Sha256::digest(content.as_bytes()));
+
+        // Crunch crunch!
+        let (s3_content, content_encoding) =
+            utils::compress(state, &content, &ValidDestination::DataStore, &self.format).await?;
+
+        let revision = self.revision + 1;
+        let s3_key = self.build_s3_key(state, revision, content_encoding);
+
+        let content_length = s3_content.len() as i32;
+        if content_length > 2 * 1024 * 1024 {
+            return Err(PastebinError::TooBig(format!("Content length is too large: {}", content_length)));
+        }
+
+        // Every revision is kept, so each one takes up more of the owner's storage
+        if let Some(room) = lock_quota(state, transaction, &self.user_id, false).await? {
+            if content_length as i64 > room {
+                return Err(over_quota());
+            }
+        }
+
+        // The original content becomes revision 0 the first time a paste is edited
+        query!(
+            r#"
+            INSERT INTO paste_revisions (paste_id, revision, s3_key, s3_content_length, content_encoding, date)
+            SELECT paste_id, revision, s3_key, s3_content_length, content_encoding, date
+            FROM pastebin
+            WHERE paste_id = $1
+            ON CONFLICT (paste_id, revision) DO NOTHING
+            "#,
+            self.paste_id
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        let updated = query!(
+            r#"
+            UPDATE pastebin
+            SET s3_key = $1, s3_content_length = $2, content_encoding = $3, content_hash = $4, revision = $5, sanitize_report = $6
+            WHERE paste_id = $7 AND revision = $8
+            "#,
+            s3_key,
+            content_length,
+            content_encoding.as_str(),
+            content_hash,
+            revision,
+            sanitize_report.as_deref(),
+            self.paste_id,
+            self.revision
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(PastebinError::Validation(
+                "This paste was changed by someone else meanwhile, try again!".to_string(),
+            ));
+        }
+
+        // A bundle's first file is the paste itself
+        query!(
+            r#"
+            UPDATE paste_files
+            SET s3_key = $1, s3_content_length = $2, content_encoding = $3
+            WHERE paste_id = $4 AND position = 0
+            "#,
+            s3_key,
+            content_length,
+            content_encoding.as_str(),
+            self.paste_id
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        query!(
+            r#"
+            INSERT INTO paste_revisions (paste_id, revision, s3_key, s3_content_length, content_encoding, date)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            self.paste_id,
+            revision,
+            s3_key,
+            content_length,
+            content_encoding.as_str(),
+            Utc::now()
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(NewRevision {
+            number: revision,
+            s3_key,
+            s3_content,
+            content_encoding,
+        })
+    }
+
+    // Only forks that would show up in search get listed on the paste page
+    pub async fn get_forks(&self, db: &PgPool) -> Result<Vec<PasteFork>, PastebinError> {
+        let forks = query_as!(
+            PasteFork,
+            r#"
+            SELECT paste_id, title, date
+            FROM pastebin
+            WHERE forked_from = $1
+            AND (expires_at IS NULL OR expires_at > NOW())
+            AND NOT burn_after_reading
+            AND visibility = 'public'
+            ORDER BY date DESC
+            LIMIT 25
+            "#,
+            self.paste_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(forks)
+    }
+
+    pub async fn get_revisions(&self, db: &PgPool) -> Result<Vec<PasteRevision>, PastebinError> {
+        let mut revisions = query_as!(
+            PasteRevision,
+            r#"
+            SELECT revision, s3_key, content_encoding AS "content_encoding: Encoding", date
+            FROM paste_revisions
+            WHERE paste_id = $1
+            ORDER BY revision DESC
+            "#,
+            self.paste_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        // Pastes that were never edited only have their original content
+        if revisions.is_empty() {
+            revisions.push(PasteRevision {
+                revision: self.revision,
+                s3_key: self.s3_key.clone(),
+                content_encoding: self.content_encoding,
+                date: self.date,
+            });
+        }
+
+        Ok(revisions)
+    }
+
+    pub async fn get_revision(&self, db: &PgPool, revision: i32) -> Result<PasteRevision, PastebinError> {
+        match query_as!(
+            PasteRevision,
+            r#"
+            SELECT revision, s3_key, content_encoding AS "content_encoding: Encoding", date
+            FROM paste_revisions
+            WHERE paste_id = $1 AND revision = $2
+            "#,
+            self.paste_id,
+            revision
+        )
+        .fetch_one(db)
+        .await
+        {
+            Ok(revision) => Ok(revision),
+            Err(RowNotFound) if revision == self.revision => Ok(PasteRevision {
+                revision: self.revision,
+                s3_key: self.s3_key.clone(),
+                content_encoding: self.content_encoding,
+                date: self.date,
+            }),
+            Err(RowNotFound) => Err(PastebinError::NotFound("Revision not found".to_string())),
+            Err(err) => Err(PastebinError::Database(err)),
+        }
+    }
+
     pub async fn delete(&self, state: &runtime::AppState) -> Result<(), PastebinError> {
         let mut transaction = state.db.begin().await?;
 
+        let revisions = query_scalar!(
+            r#"
+            SELECT s3_key
+            FROM paste_revisions
+            WHERE paste_id = $1
+            "#,
+            self.paste_id
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let files = query_scalar!(
+            r#"
+            SELECT s3_key
+            FROM paste_files
+            WHERE paste_id = $1
+            "#,
+            self.paste_id
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let attachments = query_scalar!(
+            r#"
+            SELECT s3_key
+            FROM paste_attachments
+            WHERE paste_id = $1
+            "#,
+            self.paste_id
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
         let paste = query_as!(
             DeletePaste,
             r#"
@@ -393,13 +1787,63 @@ impl Paste {
             self.paste_id
         )
         .fetch_one(&mut *transaction)
-        .await?;
+        .await
+        .map_err(|err| match err {
+            RowNotFound => PastebinError::NotFound("Paste not found".to_string()),
+            _ => PastebinError::Database(err),
+        })?;
+
+        // Every revision, bundle file and attachment has its own object, the current one is among them too
+        let mut s3_keys = vec![paste.s3_key];
+        for s3_key in revisions.into_iter().chain(files).chain(attachments) {
+            if !s3_keys.contains(&s3_key) {
+                s3_keys.push(s3_key);
+            }
+        }
 
         let fake_s3_delete = paste.gdrivedl.is_some();
-        match s3::delete(state, &paste.s3_key, fake_s3_delete).await {
+        let mut deleted = Ok(());
+        let mut deleted_keys = Vec::new();
+        for s3_key in s3_keys {
+            // Shared objects only go once the last paste pointing at them does
+            let refcount = query_scalar!(
+                r#"
+                UPDATE blobs
+                SET refcount = refcount - 1
+                WHERE s3_key = $1
+                RETURNING refcount
+                "#,
+                s3_key
+            )
+            .fetch_optional(&mut *transaction)
+            .await?;
+
+            match refcount {
+                Some(refcount) if refcount > 0 => continue,
+                Some(_) => {
+                    query!(r#"DELETE FROM blobs WHERE s3_key = $1"#, s3_key)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+                None => {}
+            }
+
+            deleted = s3::delete(state, &s3_key, fake_s3_delete).await;
+            if deleted.is_err() {
+                break;
+            }
+            deleted_keys.push(s3_key);
+        }
+
+        match deleted {
             Ok(()) => match transaction.commit().await {
                 Ok(_) => {
-                    let _ = cloudflare::queue().insert_async(paste.s3_key).await;
+                    // Don't let update_views write back views for a paste that's gone
+                    counter().remove_async(&self.paste_id).await;
+
+                    for s3_key in deleted_keys {
+                        let _ = cloudflare::queue().insert_async(s3_key).await;
+                    }
                     cloudflare::purge_cache(state, false).await;
                     Ok(())
                 }
@@ -425,15 +1869,18 @@ impl Paste {
     ) -> Result<Vec<SearchPaste>, PastebinError> {
         let pastes = query_as!(
             SearchPaste,
-            "
-            SELECT paste_id, title, tags, format, date, views
+            r#"
+            SELECT paste_id, title, tags, format AS "format: PasteFormat", NULLIF(split_part(format, ':', 2), '') AS language, date, views
             FROM pastebin
             WHERE
                 tags @> $1::varchar[]
+                AND (expires_at IS NULL OR expires_at > NOW())
+                AND NOT burn_after_reading
+                AND visibility = 'public'
             ORDER BY date DESC
             LIMIT 10
             OFFSET $2
-            ",
+            "#,
             tags,
             (page - 1) * 10
         )
@@ -443,11 +1890,304 @@ impl Paste {
         Ok(pastes)
     }
 
-    pub fn get_content_url(&self, s3_bucket_url: &str) -> String {
-        if self.gdrivedl.is_none() {
-            format!("{}{}", s3_bucket_url, self.s3_key)
+    pub async fn list_for_user(
+        db: &PgPool,
+        user_id: &str,
+        page: i64,
+        per_page: i64,
+    ) -> Result<(Vec<UserPaste>, bool), PastebinError> {
+        // One extra tells us whether there's another page after this one
+        let mut pastes = query_as!(
+            UserPaste,
+            r#"
+            SELECT paste_id, title, tags, format AS "format: PasteFormat", date, views, visibility
+            FROM pastebin
+            WHERE
+                user_id = $1
+                AND (expires_at IS NULL OR expires_at > NOW())
+            ORDER BY date DESC
+            LIMIT $2
+            OFFSET $3
+            "#,
+            user_id,
+            per_page + 1,
+            (page - 1) * per_page
+        )
+        .fetch_all(db)
+        .await?;
+
+        let more = pastes.len() as i64 > per_page;
+        pastes.truncate(per_page as usize);
+
+        Ok((pastes, more))
+    }
+
+    pub async fn get_content(&self, state: &runtime::AppState) -> Result<String, PastebinError> {
+        let content = match &self.gdrivedl {
+            Some(gdrivedl_url) => {
+                let response = reqwest::get(gdrivedl_url)
+                    .await
+                    .map_err(|err| PastebinError::ExternalService(err.to_string()))?;
+
+                if !response.status().is_success() {
+                    return Err(PastebinError::ExternalService(
+                        "Google Drive wouldn't talk to us!".to_string(),
+                    ));
+                }
+
+                response
+                    .bytes()
+                    .await
+                    .map_err(|err| PastebinError::ExternalService(err.to_string()))?
+                    .to_vec()
+            }
+            None => s3::get(state, &self.s3_key)
+                .await
+                .map_err(|err| PastebinError::Storage(format!("Failed to download from S3: {}", err)))?,
+        };
+
+        utils::decompress(content, self.content_encoding).await
+    }
+
+    pub async fn get_files(&self, db: &PgPool) -> Result<Vec<PasteFile>, PastebinError> {
+        let files = query_as!(
+            PasteFile,
+            r#"
+            SELECT position, name, format AS "format: PasteFormat", s3_key, content_encoding AS "content_encoding: Encoding"
+            FROM paste_files
+            WHERE paste_id = $1
+            ORDER BY position
+            "#,
+            self.paste_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(files)
+    }
+
+    pub async fn get_file(&self, db: &PgPool, position: i32) -> Result<PasteFile, PastebinError> {
+        query_as!(
+            PasteFile,
+            r#"
+            SELECT position, name, format AS "format: PasteFormat", s3_key, content_encoding AS "content_encoding: Encoding"
+            FROM paste_files
+            WHERE paste_id = $1 AND position = $2
+            "#,
+            self.paste_id,
+            position
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|err| match err {
+            RowNotFound => PastebinError::NotFound("File not found".to_string()),
+            _ => PastebinError::Database(err),
+        })
+    }
+
+    pub async fn get_file_content(&self, state: &runtime::AppState, file: &PasteFile) -> Result<String, PastebinError> {
+        // The first file is the paste itself and may have been edited since
+        if file.position == 0 {
+            return self.get_content(state).await;
+        }
+
+        let content = s3::get(state, &file.s3_key)
+            .await
+            .map_err(|err| PastebinError::Storage(format!("Failed to download from S3: {}", err)))?;
+
+        utils::decompress(content, file.content_encoding).await
+    }
+
+    // Attachments are kept on S3 even for Drive pastes, so those and burnable ones go without
+    pub fn can_attach(&self) -> bool {
+        self.gdrivedl.is_none() && !self.burn_after_reading
+    }
+
+    pub async fn get_attachments(&self, db: &PgPool) -> Result<Vec<PasteAttachment>, PastebinError> {
+        let attachments = query_as!(
+            PasteAttachment,
+            r#"
+            SELECT position, content_type, s3_key
+            FROM paste_attachments
+            WHERE paste_id = $1
+            ORDER BY position
+            "#,
+            self.paste_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(attachments)
+    }
+
+    pub async fn get_attachment(&self, db: &PgPool, position: i32) -> Result<PasteAttachment, PastebinError> {
+        query_as!(
+            PasteAttachment,
+            r#"
+            SELECT position, content_type, s3_key
+            FROM paste_attachments
+            WHERE paste_id = $1 AND position = $2
+            "#,
+            self.paste_id,
+            position
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|err| match err {
+            RowNotFound => PastebinError::NotFound("Attachment not found".to_string()),
+            _ => PastebinError::Database(err),
+        })
+    }
+
+    // Locked and private pastes have their attachments served through us, same as their content
+    pub fn get_attachment_url(&self, attachment: &PasteAttachment, s3_bucket_url: &str) -> String {
+        if self.keeps_content_private() {
+            format!("/pastebin/{}/attachments/{}", self.paste_id, attachment.position)
         } else {
+            format!("{}/{}", s3_bucket_url, attachment.s3_key)
+        }
+    }
+
+    pub async fn attach(&self, state: &runtime::AppState, images: Vec<&[u8]>) -> Result<(), PastebinError> {
+        if !self.can_attach() {
+            return Err(PastebinError::Validation(
+                "Only pastes kept in our datastore that aren't burnt after reading can have attachments!".to_string(),
+            ));
+        }
+
+        if images.is_empty() {
+            return Err(PastebinError::Validation("There's nothing to attach!".to_string()));
+        }
+
+        let mut uploads = Vec::new();
+        for image in images.iter() {
+            let (content_type, ext) = image_type(image).ok_or_else(|| {
+                PastebinError::Validation("Only PNG, JPEG and WebP images can be attached!".to_string())
+            })?;
+
+            if image.len() > MAX_ATTACHMENT_SIZE {
+                return Err(PastebinError::TooBig(format!("Attachment is too large: {}", image.len())));
+            }
+
+            uploads.push((content_type, ext, image.to_vec()));
+        }
+
+        let mut transaction = state.db.begin().await?;
+
+        // The quota is locked before the paste, the same order edits take them in
+        if let Some(room) = lock_quota(state, &mut transaction, &self.user_id, false).await? {
+            let total: usize = uploads.iter().map(|(_, _, image)| image.len()).sum();
+            if total as i64 > room {
+                return Err(over_quota());
+            }
+        }
+
+        // Hold the paste row so concurrent attaches count and number attachments one after the other
+        query_scalar!(
+            "SELECT paste_id FROM pastebin WHERE paste_id = $1 FOR UPDATE",
+            self.paste_id
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|err| match err {
+            RowNotFound => PastebinError::NotFound("Paste not found".to_string()),
+            _ => PastebinError::Database(err),
+        })?;
+
+        let existing: i64 = query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM paste_attachments WHERE paste_id = $1"#,
+            self.paste_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        if existing + uploads.len() as i64 > MAX_ATTACHMENTS {
+            return Err(PastebinError::Validation(format!(
+                "Pastes can have up to {} attachments!",
+                MAX_ATTACHMENTS
+            )));
+        }
+
+        let next: i32 = query_scalar!(
+            r#"SELECT COALESCE(MAX(position) + 1, 0) AS "next!" FROM paste_attachments WHERE paste_id = $1"#,
+            self.paste_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        let mut uploaded = Vec::new();
+        for (n, (content_type, ext, image)) in uploads.into_iter().enumerate() {
+            let position = next + n as i32;
+            let s3_key = self.build_object_key(state, &format!(".a{}", position), ext, Encoding::Identity);
+            let content_length = image.len() as i32;
+
+            if let Err(err) = query!(
+                r#"
+                INSERT INTO paste_attachments (paste_id, position, content_type, s3_key, s3_content_length, date)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+                self.paste_id,
+                position,
+                content_type,
+                s3_key,
+                content_length,
+                Utc::now()
+            )
+            .execute(&mut *transaction)
+            .await
+            {
+                discard_objects(state, &uploaded).await;
+                return Err(PastebinError::Database(err));
+            }
+
+            if let Err(err) = s3::upload(
+                state,
+                &s3_key,
+                image,
+                content_type,
+                "identity",
+                &self.title,
+                &self.tags,
+                &format!("{}.a{}.{}", self.paste_id, position, ext),
+                false,
+            )
+            .await
+            {
+                discard_objects(state, &uploaded).await;
+                return Err(PastebinError::Storage(format!("Failed to upload to S3: {}", err)));
+            }
+            uploaded.push(s3_key);
+        }
+
+        if let Err(err) = transaction.commit().await {
+            discard_objects(state, &uploaded).await;
+            return Err(PastebinError::Database(err));
+        }
+        Ok(())
+    }
+
+    // Fetch the content one last time and then burn the paste, only one reader gets to win
+    pub async fn burn(&self, state: &runtime::AppState) -> Result<String, PastebinError> {
+        let content = self.get_content(state).await?;
+        self.delete(state).await?;
+        Ok(content)
+    }
+
+    // Private pastes are only ever shown to the signed in user who owns them
+    pub fn can_view(&self, user_id: &Option<String>) -> bool {
+        match self.visibility {
+            PasteVisibility::Private => user_id.is_some() && user_id == &self.user_id,
+            _ => true,
+        }
+    }
+
+    pub fn get_content_url(&self, s3_bucket_url: &str) -> String {
+        if self.gdrivedl.is_some() {
             format!("/pastebinc/{}/content", self.paste_id)
+        } else if self.keeps_content_private() {
+            format!("/pastebinc/{}/content?revision={}", self.paste_id, self.revision)
+        } else {
+            format!("{}{}", s3_bucket_url, self.s3_key)
         }
     }
 
@@ -459,13 +2199,54 @@ impl Paste {
     }
 
     pub fn get_format(&self) -> String {
-        match self.format {
-            PasteFormat::Ansi(_) => "log".to_string(),
-            PasteFormat::Text(_) => "plain".to_string(),
-            PasteFormat::Html(_) => "html".to_string(),
+        self.format.key()
+    }
+
+    pub fn get_language(&self) -> Option<Language> {
+        self.format.language()
+    }
+
+    pub fn get_expires_at(&self) -> String {
+        self.expires_at
+            .map(|expires_at| expires_at.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn get_ext(&self) -> &'static str {
+        self.format.ext()
+    }
+
+    // Server side rendering for clients that can't run our Javascript
+    // Rendering a big paste takes a while, keep it off the async workers
+    pub async fn render_html(&self, content: String) -> Option<String> {
+        let render = self.format.spec().render?;
+        let language = self.get_language();
+        match tokio::task::spawn_blocking(move || render(&content, language)).await {
+            Ok(rendered) => Some(rendered),
+            Err(err) => {
+                error!("Rendering task failed: {}", err);
+                None
+            }
         }
     }
 
+    // Something safe to put in a Content-Disposition header
+    pub fn get_filename(&self) -> String {
+        let name: String = self
+            .get_title()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let name = name.trim_matches('_');
+        let name = if name.is_empty() { "paste" } else { name };
+
+        format!("{}.{}", name, self.get_ext())
+    }
+
+    pub fn get_content_type(&self) -> String {
+        self.format.spec().content_type.to_string()
+    }
+
     pub fn get_tags(&self) -> Vec<String> {
         self.tags.clone().unwrap_or_default()
     }
@@ -481,6 +2262,11 @@ impl Paste {
             .or_insert_with(|| self.views + 1)
     }
 
+    // Views as they stand, without counting this look as one
+    pub async fn peek_views(paste_id: &str, views: i64) -> i64 {
+        counter().read_async(paste_id, |_, views| *views).await.unwrap_or(views)
+    }
+
     async fn save_views(&self, db: &PgPool, views: i64) {
         let now = Utc::now();
         match query!(
@@ -536,3 +2322,44 @@ pub async fn update_views(state: &runtime::AppState, do_sleep: bool) {
         }
     }
 }
+
+pub async fn reap_expired(state: &runtime::AppState, do_sleep: bool) {
+    loop {
+        if do_sleep {
+            sleep(Duration::from_secs(state.config.reap_expired_interval)).await;
+        }
+
+        let pastes = match query_as!(
+            Paste,
+            r#"
+            SELECT paste_id, user_id, session_id, title, tags, format AS "format: PasteFormat", date, gdriveid, gdrivedl, s3_key, content_encoding AS "content_encoding: Encoding", rcscore, views, last_seen, expires_at, burn_after_reading, visibility, password_hash, revision, forked_from, sanitize_report
+            FROM pastebin
+            WHERE expires_at <= NOW()
+            LIMIT 100
+            "#
+        )
+        .fetch_all(&state.db)
+        .await
+        {
+            Ok(pastes) => pastes,
+            Err(err) => {
+                error!("Failed to fetch expired pastes: {}", err);
+                vec![]
+            }
+        };
+
+        if !pastes.is_empty() {
+            info!("About to reap {} expired paste(s)..", pastes.len());
+        }
+
+        for paste in pastes.iter() {
+            if let Err(err) = paste.delete(state).await {
+                error!("Failed to reap expired paste {}: {}", paste.paste_id, err);
+            }
+        }
+
+        if !do_sleep {
+            break;
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::runtime;
 use aws_credential_types::Credentials;
 use aws_sdk_s3 as s3;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 use std::sync::OnceLock;
 use tracing::error;
 
@@ -111,3 +113,168 @@ pub async fn delete(state: &runtime::AppState, key: &str, fake_it: bool) -> Resu
 
     Ok(())
 }
+
+// Copies keep the content type, encoding and metadata the object was uploaded with
+pub async fn copy(state: &runtime::AppState, from: &str, to: &str) -> Result<(), String> {
+    match get_client()
+        .copy_object()
+        .bucket(state.config.s3_bucket.clone())
+        .copy_source(format!("{}/{}", state.config.s3_bucket, from))
+        .key(to)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("Failed to copy within S3: {}", err);
+            Err(err.to_string())
+        }
+    }
+}
+
+pub async fn get(state: &runtime::AppState, key: &str) -> Result<Vec<u8>, String> {
+    match get_stream(state, key).await?.collect().await {
+        Ok(content) => Ok(content.into_bytes().to_vec()),
+        Err(err) => {
+            error!("Failed to read S3 object body: {}", err);
+            Err(err.to_string())
+        }
+    }
+}
+
+pub async fn get_stream(state: &runtime::AppState, key: &str) -> Result<ByteStream, String> {
+    let object = match get_client()
+        .get_object()
+        .bucket(state.config.s3_bucket.clone())
+        .key(key)
+        .send()
+        .await
+    {
+        Ok(object) => object,
+        Err(err) => {
+            error!("Failed to download from S3: {}", err);
+            return Err(err.to_string());
+        }
+    };
+
+    Ok(object.body)
+}
+
+// Multipart uploads for objects we only get to see a part at a time
+#[allow(clippy::too_many_arguments)]
+pub async fn create_multipart(
+    state: &runtime::AppState,
+    key: &str,
+    content_type: &str,
+    content_encoding: &str,
+    title: &Option<String>,
+    tags: &Option<Vec<String>>,
+    filename: &str,
+) -> Result<String, String> {
+    let title = match title {
+        Some(title) => title,
+        None => "",
+    };
+
+    let tags = tags
+        .as_ref()
+        .map(|tags| tags.join(", "))
+        .unwrap_or_default();
+
+    let filename: String = filename.chars().filter(|c| c != &'~').collect();
+    match get_client()
+        .create_multipart_upload()
+        .bucket(state.config.s3_bucket.clone())
+        .key(key)
+        .content_type(content_type)
+        .content_encoding(content_encoding)
+        .content_disposition(format!(
+            "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+            filename, filename
+        ))
+        .metadata("title", title)
+        .metadata("tags", tags)
+        .send()
+        .await
+    {
+        Ok(output) => output
+            .upload_id()
+            .map(|upload_id| upload_id.to_string())
+            .ok_or_else(|| "S3 didn't give us an upload ID".to_string()),
+        Err(err) => {
+            error!("Failed to start multipart upload to S3: {}", err);
+            Err(err.to_string())
+        }
+    }
+}
+
+// Returns the part's ETag, which completing the upload needs
+pub async fn upload_part(
+    state: &runtime::AppState,
+    key: &str,
+    upload_id: &str,
+    part_number: i32,
+    content: Vec<u8>,
+) -> Result<String, String> {
+    match get_client()
+        .upload_part()
+        .bucket(state.config.s3_bucket.clone())
+        .key(key)
+        .upload_id(upload_id)
+        .part_number(part_number)
+        .body(content.into())
+        .send()
+        .await
+    {
+        Ok(output) => Ok(output.e_tag().unwrap_or_default().to_string()),
+        Err(err) => {
+            error!("Failed to upload part to S3: {}", err);
+            Err(err.to_string())
+        }
+    }
+}
+
+pub async fn complete_multipart(
+    state: &runtime::AppState,
+    key: &str,
+    upload_id: &str,
+    parts: &[(i32, String)],
+) -> Result<(), String> {
+    let parts = parts
+        .iter()
+        .map(|(part_number, e_tag)| CompletedPart::builder().part_number(*part_number).e_tag(e_tag).build())
+        .collect();
+
+    match get_client()
+        .complete_multipart_upload()
+        .bucket(state.config.s3_bucket.clone())
+        .key(key)
+        .upload_id(upload_id)
+        .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+        .send()
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("Failed to complete multipart upload to S3: {}", err);
+            Err(err.to_string())
+        }
+    }
+}
+
+pub async fn abort_multipart(state: &runtime::AppState, key: &str, upload_id: &str) -> Result<(), String> {
+    match get_client()
+        .abort_multipart_upload()
+        .bucket(state.config.s3_bucket.clone())
+        .key(key)
+        .upload_id(upload_id)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("Failed to abort multipart upload to S3: {}", err);
+            Err(err.to_string())
+        }
+    }
+}
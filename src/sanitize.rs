@@ -0,0 +1,525 @@
+use std::collections::BTreeMap;
+
+// What Mudlet and Nexus log exports are made of, plus a little basic formatting
+const ALLOWED_TAGS: [&str; 23] = [
+    "b", "body", "br", "code", "div", "em", "font", "head", "hr", "html", "i", "p", "pre", "s",
+    "small", "span", "strong", "style", "sub", "sup", "title", "tt", "u",
+];
+
+const VOID_TAGS: [&str; 2] = ["br", "hr"];
+
+const ALLOWED_ATTRIBUTES: [&str; 6] = ["align", "class", "color", "face", "size", "style"];
+
+const ALLOWED_PROPERTIES: [&str; 19] = [
+    "background",
+    "background-color",
+    "color",
+    "display",
+    "font",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "letter-spacing",
+    "line-height",
+    "margin",
+    "padding",
+    "text-align",
+    "text-decoration",
+    "white-space",
+    "word-break",
+    "word-wrap",
+];
+
+// Whatever is inside these runs up to the closing tag, markup or not
+const RAW_TEXT_TAGS: [&str; 10] = [
+    "iframe", "noembed", "noframes", "noscript", "plaintext", "script", "style", "textarea",
+    "title", "xmp",
+];
+
+// Gone along with everything inside them
+const DROPPED_TAGS: [&str; 11] = [
+    "applet", "audio", "button", "canvas", "frameset", "math", "object", "select", "svg",
+    "template", "video",
+];
+
+// Anything in CSS that could reach outside the paste or run code
+const UNSAFE_CSS: [&str; 10] = [
+    "url(", "expression", "javascript", "@import", "image-set", "binding", "behavior", "\\", "<",
+    "/*",
+];
+
+struct Tag {
+    name: String,
+    end: bool,
+    self_closing: bool,
+    attributes: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Sanitizer {
+    output: String,
+    removed: BTreeMap<String, usize>,
+    open: Vec<String>,
+    skipping: Option<(String, usize)>,
+}
+
+/// Run HTML through our allow-list, returning what's left and a report of what was taken out
+pub fn html(input: &str) -> (String, Vec<String>) {
+    let mut sanitizer = Sanitizer::default();
+    let mut pos = 0;
+
+    // Closing tags are matched case insensitively, lowercasing ASCII keeps every offset the same
+    let lowercase = input.to_ascii_lowercase();
+
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let lt = match rest.find('<') {
+            Some(lt) => lt,
+            None => {
+                sanitizer.text(rest);
+                break;
+            }
+        };
+
+        sanitizer.text(&rest[..lt]);
+        pos += lt;
+
+        let rest = &input[pos..];
+        if rest.starts_with("<!--") {
+            pos += rest.find("-->").map(|i| i + 3).unwrap_or(rest.len());
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos += rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+        } else if let Some((tag, length)) = parse_tag(rest) {
+            pos += length;
+            sanitizer.tag(tag, input, &lowercase, &mut pos);
+        } else {
+            sanitizer.text("<");
+            pos += 1;
+        }
+    }
+
+    // Close whatever was left hanging
+    while let Some(name) = sanitizer.open.pop() {
+        sanitizer.output.push_str(&format!("</{}>", name));
+    }
+
+    let report = sanitizer
+        .removed
+        .into_iter()
+        .map(|(what, count)| if count > 1 { format!("{} ({})", what, count) } else { what })
+        .collect();
+
+    (sanitizer.output, report)
+}
+
+impl Sanitizer {
+    fn remove(&mut self, what: String) {
+        *self.removed.entry(what).or_insert(0) += 1;
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skipping.is_some() {
+            return;
+        }
+
+        for c in text.chars() {
+            match c {
+                '<' => self.output.push_str("&lt;"),
+                '>' => self.output.push_str("&gt;"),
+                _ => self.output.push(c),
+            }
+        }
+    }
+
+    fn tag(&mut self, tag: Tag, input: &str, lowercase: &str, pos: &mut usize) {
+        let name = tag.name.as_str();
+
+        if let Some((skipped, depth)) = &mut self.skipping {
+            if skipped == name {
+                if tag.end {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        self.skipping = None;
+                    }
+                } else if !tag.self_closing {
+                    *depth += 1;
+                }
+            }
+            return;
+        }
+
+        if RAW_TEXT_TAGS.contains(&name) {
+            if tag.end {
+                return;
+            }
+
+            let rest = &input[*pos..];
+            let (content, consumed) = match lowercase[*pos..].find(&format!("</{}", name)) {
+                Some(close) => {
+                    let end = rest[close..].find('>').map(|i| close + i + 1).unwrap_or(rest.len());
+                    (&rest[..close], end)
+                }
+                None => (rest, rest.len()),
+            };
+            *pos += consumed;
+
+            match name {
+                "style" => self.style_element(content),
+                "title" => {
+                    self.output.push_str("<title>");
+                    self.text(content);
+                    self.output.push_str("</title>");
+                }
+                _ => self.remove(format!("<{}>", name)),
+            }
+            return;
+        }
+
+        if DROPPED_TAGS.contains(&name) {
+            if !tag.end {
+                self.remove(format!("<{}>", name));
+                if !tag.self_closing {
+                    self.skipping = Some((tag.name.clone(), 1));
+                }
+            }
+            return;
+        }
+
+        // Unknown tags go away but whatever text they held stays
+        if !ALLOWED_TAGS.contains(&name) {
+            if !tag.end {
+                self.remove(format!("<{}>", name));
+            }
+            return;
+        }
+
+        if tag.end {
+            if let Some(index) = self.open.iter().rposition(|open| open == name) {
+                for open in self.open.drain(index..).rev() {
+                    self.output.push_str(&format!("</{}>", open));
+                }
+            }
+            return;
+        }
+
+        let mut output = format!("<{}", name);
+        for (attribute, value) in tag.attributes.iter() {
+            if let Some(attribute) = self.attribute(attribute, value) {
+                output.push_str(&attribute);
+            }
+        }
+        output.push('>');
+        self.output.push_str(&output);
+
+        if VOID_TAGS.contains(&name) {
+            return;
+        }
+        if tag.self_closing {
+            self.output.push_str(&format!("</{}>", name));
+        } else {
+            self.open.push(tag.name);
+        }
+    }
+
+    fn attribute(&mut self, name: &str, value: &str) -> Option<String> {
+        if name.starts_with("on") {
+            self.remove("event handlers".to_string());
+            return None;
+        }
+
+        if !ALLOWED_ATTRIBUTES.contains(&name) {
+            self.remove(format!("{} attributes", name));
+            return None;
+        }
+
+        let value = if name == "style" {
+            self.style(value)
+        } else {
+            value.to_string()
+        };
+
+        if value.is_empty() {
+            return None;
+        }
+
+        Some(format!(" {}=\"{}\"", name, escape_attribute(&value)))
+    }
+
+    fn style(&mut self, style: &str) -> String {
+        let mut declarations = Vec::new();
+
+        for declaration in style.split(';') {
+            let (property, value) = match declaration.split_once(':') {
+                Some((property, value)) => (property.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            if !ALLOWED_PROPERTIES.contains(&property.as_str()) {
+                self.remove(format!("{} styles", property));
+                continue;
+            }
+
+            if is_unsafe_css(value) {
+                self.remove("styles with external resources".to_string());
+                continue;
+            }
+
+            declarations.push(format!("{}: {}", property, value));
+        }
+
+        declarations.join("; ")
+    }
+
+    fn style_element(&mut self, content: &str) {
+        if is_unsafe_css(content) {
+            self.remove("<style> with external resources".to_string());
+            return;
+        }
+
+        self.output.push_str("<style>");
+        self.output.push_str(content);
+        self.output.push_str("</style>");
+    }
+}
+
+fn is_unsafe_css(css: &str) -> bool {
+    let css = css.to_ascii_lowercase();
+    UNSAFE_CSS.iter().any(|unsafe_css| css.contains(unsafe_css))
+}
+
+fn parse_tag(input: &str) -> Option<(Tag, usize)> {
+    let bytes = input.as_bytes();
+    let length = bytes.len();
+    let mut i = 1;
+
+    let end = bytes.get(1) == Some(&b'/');
+    if end {
+        i += 1;
+    }
+
+    let start = i;
+    while i < length && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+        i += 1;
+    }
+    if i == start || !bytes[start].is_ascii_alphabetic() {
+        return None;
+    }
+
+    let mut tag = Tag {
+        name: input[start..i].to_ascii_lowercase(),
+        end,
+        self_closing: false,
+        attributes: Vec::new(),
+    };
+
+    loop {
+        while i < length && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        if i >= length {
+            break;
+        }
+
+        match bytes[i] {
+            b'>' => {
+                i += 1;
+                break;
+            }
+            b'/' => {
+                i += 1;
+                if bytes.get(i) == Some(&b'>') {
+                    tag.self_closing = true;
+                }
+                continue;
+            }
+            b'=' => {
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let name_start = i;
+        while i < length && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'>' | b'/' | b'=') {
+            i += 1;
+        }
+        let name = input[name_start..i].to_ascii_lowercase();
+
+        while i < length && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let mut value = "";
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < length && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+
+            match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    i += 1;
+                    let value_start = i;
+                    while i < length && bytes[i] != quote {
+                        i += 1;
+                    }
+                    value = &input[value_start..i];
+                    if i < length {
+                        i += 1;
+                    }
+                }
+                _ => {
+                    let value_start = i;
+                    while i < length && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = &input[value_start..i];
+                }
+            }
+        }
+
+        tag.attributes.push((name, decode_entities(value)));
+    }
+
+    Some((tag, i))
+}
+
+// Browsers decode these before the value means anything, so we have to as well
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(amp) = rest.find('&') {
+        output.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let (decoded, consumed) = decode_entity(rest);
+        match decoded {
+            Some(c) => {
+                output.push(c);
+                rest = &rest[consumed..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn decode_entity(input: &str) -> (Option<char>, usize) {
+    if let Some(numeric) = input.strip_prefix("&#") {
+        let (digits, radix, skip) = match numeric.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 3),
+            None => (numeric, 10, 2),
+        };
+        let length = digits.chars().take_while(|c| c.is_digit(radix)).count();
+        if length == 0 {
+            return (None, 0);
+        }
+
+        let c = u32::from_str_radix(&digits[..length], radix)
+            .ok()
+            .and_then(char::from_u32)
+            .unwrap_or('\u{FFFD}');
+        let semicolon = usize::from(digits[length..].starts_with(';'));
+        return (Some(c), skip + length + semicolon);
+    }
+
+    for (name, c) in [("&amp;", '&'), ("&lt;", '<'), ("&gt;", '>'), ("&quot;", '"'), ("&apos;", '\''), ("&nbsp;", '\u{A0}')] {
+        if input.get(..name.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(name)) {
+            return (Some(c), name.len());
+        }
+    }
+
+    (None, 0)
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '"' => output.push_str("&quot;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn keeps_allowed_markup() {
+        let (output, report) = html(r#"<p align="center"><b>bold</b> <font color="red">red</font></p>"#);
+        assert_eq!(output, r#"<p align="center"><b>bold</b> <font color="red">red</font></p>"#);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn drops_scripts_and_handlers() {
+        let (output, report) = html(r#"<div onclick="alert(1)">hi<script>alert(2)</script></div>"#);
+        assert_eq!(output, "<div>hi</div>");
+        assert_eq!(report, vec!["<script>", "event handlers"]);
+    }
+
+    #[test]
+    fn closes_raw_text_case_insensitively() {
+        let (output, _) = html("<SCRIPT>alert(1)</ScRiPt>after");
+        assert_eq!(output, "after");
+    }
+
+    #[test]
+    fn drops_everything_inside_dropped_tags() {
+        let (output, report) = html("<svg><svg></svg><text>gone</text></svg>kept");
+        assert_eq!(output, "kept");
+        assert_eq!(report, vec!["<svg>"]);
+    }
+
+    #[test]
+    fn filters_styles() {
+        let (output, report) = html(r#"<span style="color: red; position: fixed; background: url(x)">x</span>"#);
+        assert_eq!(output, r#"<span style="color: red">x</span>"#);
+        assert_eq!(report, vec!["position styles", "styles with external resources"]);
+    }
+
+    #[test]
+    fn decodes_entities_before_checking_styles() {
+        let (output, _) = html(r#"<span style="background: u&#114;l(x)">x</span>"#);
+        assert_eq!(output, "<span>x</span>");
+    }
+
+    #[test]
+    fn escapes_stray_brackets_and_closes_open_tags() {
+        let (output, _) = html("<b>1 < 2 > 0");
+        assert_eq!(output, "<b>1 &lt; 2 &gt; 0</b>");
+    }
+
+    #[test]
+    fn removes_comments_and_doctypes() {
+        let (output, report) = html("<!DOCTYPE html><!-- hidden --><p>shown</p>");
+        assert_eq!(output, "<p>shown</p>");
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn many_raw_text_tags_stay_fast() {
+        let input = "<xmp></xmp>".repeat(200_000);
+        let started = Instant::now();
+        let (output, report) = html(&input);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(output.is_empty());
+        assert_eq!(report, vec!["<xmp> (200000)"]);
+    }
+}
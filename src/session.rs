@@ -4,7 +4,15 @@ use std::sync::Arc;
 use tower_cookies::{Cookie, Cookies};
 
 pub fn update_session(state: &Arc<runtime::AppState>, cookies: &Cookies, paste_id: &str) {
-    let mut paste_ids = get_session(state, cookies);
+    add_paste_id(state, cookies, "_pb_session", paste_id);
+}
+
+pub fn update_unlocked(state: &Arc<runtime::AppState>, cookies: &Cookies, paste_id: &str) {
+    add_paste_id(state, cookies, "_pb_unlocked", paste_id);
+}
+
+fn add_paste_id(state: &Arc<runtime::AppState>, cookies: &Cookies, name: &str, paste_id: &str) {
+    let mut paste_ids = get_session(state, cookies, name);
 
     paste_ids.push_back(paste_id.to_owned());
     if paste_ids.len() > 10 {
@@ -15,7 +23,7 @@ pub fn update_session(state: &Arc<runtime::AppState>, cookies: &Cookies, paste_i
 
     let cookies = cookies.private(&state.cookie_key);
     cookies.add(
-        Cookie::build((utils::get_cookie_name(state, "_pb_session"), paste_ids))
+        Cookie::build((utils::get_cookie_name(state, name), paste_ids))
             .path("/pastebin/")
             .http_only(true)
             .secure(state.config.cookie_secure)
@@ -24,9 +32,9 @@ pub fn update_session(state: &Arc<runtime::AppState>, cookies: &Cookies, paste_i
     );
 }
 
-fn get_session(state: &Arc<runtime::AppState>, cookies: &Cookies) -> VecDeque<String> {
+fn get_session(state: &Arc<runtime::AppState>, cookies: &Cookies, name: &str) -> VecDeque<String> {
     let cookies = cookies.private(&state.cookie_key);
-    let session = cookies.get(utils::get_cookie_name(state, "_pb_session").as_str());
+    let session = cookies.get(utils::get_cookie_name(state, name).as_str());
 
     let paste_ids = match session {
         Some(pids) => {
@@ -43,6 +51,15 @@ pub fn is_paste_in_session(
     cookies: &Cookies,
     paste_id: &str,
 ) -> bool {
-    let paste_ids = get_session(state, cookies);
+    let paste_ids = get_session(state, cookies, "_pb_session");
+    paste_ids.contains(&paste_id.to_owned())
+}
+
+pub fn is_paste_unlocked(
+    state: &Arc<runtime::AppState>,
+    cookies: &Cookies,
+    paste_id: &str,
+) -> bool {
+    let paste_ids = get_session(state, cookies, "_pb_unlocked");
     paste_ids.contains(&paste_id.to_owned())
 }
@@ -1,4 +1,6 @@
-use crate::paste::Paste;
+use crate::api::APIToken;
+use crate::diff::DiffLine;
+use crate::paste::{Paste, PasteAttachment, PasteFile, PasteFork, PasteRevision};
 use askama::Template;
 use axum::{
     http::StatusCode,
@@ -57,12 +59,87 @@ pub struct PastebinTemplate {
 #[template(path = "paste.html.j2")]
 pub struct PasteTemplate {
     pub static_domain: String,
+    pub recaptcha_key: String,
     pub content_url: String,
     pub csrf_token: String,
     pub user_id: Option<String>,
     pub paste: Paste,
     pub views: i64,
     pub owned: bool,
+    pub content: Option<String>,
+    pub rendered: Option<String>,
+    pub latest_revision: i32,
+    pub forks: Vec<PasteFork>,
+    pub files: Vec<PasteFile>,
+    pub attachments: Vec<(PasteAttachment, String)>,
+}
+
+#[derive(Template)]
+#[template(path = "paste-file.html.j2")]
+pub struct PasteFileTemplate {
+    pub file: PasteFile,
+    pub content: String,
+    pub rendered: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "paste-revisions.html.j2")]
+pub struct PasteRevisionsTemplate {
+    pub static_domain: String,
+    pub user_id: Option<String>,
+    pub paste: Paste,
+    pub revisions: Vec<PasteRevision>,
+}
+
+#[derive(Template)]
+#[template(path = "account-usage.html.j2")]
+pub struct AccountUsageTemplate {
+    pub static_domain: String,
+    pub user_id: Option<String>,
+    pub tier: String,
+    pub pastes: i64,
+    pub max_pastes: Option<i64>,
+    pub pastes_percent: i64,
+    pub bytes: String,
+    pub max_bytes: Option<String>,
+    pub bytes_percent: i64,
+}
+
+#[derive(Template)]
+#[template(path = "paste-burn.html.j2")]
+pub struct PasteBurnTemplate {
+    pub static_domain: String,
+    pub csrf_token: String,
+    pub user_id: Option<String>,
+    pub paste: Paste,
+}
+
+#[derive(Template)]
+#[template(path = "paste-locked.html.j2")]
+pub struct PasteLockedTemplate {
+    pub static_domain: String,
+    pub csrf_token: String,
+    pub user_id: Option<String>,
+    pub paste: Paste,
+    pub error: String,
+}
+
+#[derive(Template)]
+#[template(path = "export.html.j2")]
+pub struct ExportTemplate {
+    pub title: String,
+    pub body: String,
+    pub preformatted: bool,
+}
+
+#[derive(Template)]
+#[template(path = "diff.html.j2")]
+pub struct DiffTemplate {
+    pub static_domain: String,
+    pub user_id: Option<String>,
+    pub old: Paste,
+    pub new: Paste,
+    pub lines: Vec<DiffLine>,
 }
 
 #[derive(Template)]
@@ -77,7 +154,18 @@ pub struct SearchTemplate {
 pub struct APIAboutTemplate {
     pub static_domain: String,
     pub user_id: Option<String>,
-    pub api_key: String,
+    pub upload_max_size: usize,
+}
+
+#[derive(Template)]
+#[template(path = "api-tokens.html.j2")]
+pub struct APITokensTemplate {
+    pub static_domain: String,
+    pub csrf_token: String,
+    pub user_id: Option<String>,
+    pub tokens: Vec<APIToken>,
+    pub new_token: Option<String>,
+    pub max_tokens: i64,
 }
 
 #[derive(Template)]
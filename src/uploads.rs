@@ -0,0 +1,447 @@
+use crate::compression::StreamEncoder;
+use crate::errors::PastebinError;
+use crate::highlight;
+use crate::paste;
+use crate::paste::{Paste, PasteFormat};
+use crate::runtime;
+use crate::s3;
+use axum::body::Bytes;
+use rand::RngExt;
+use scc::HashMap;
+use sha2::{Digest, Sha256};
+use std::ops::{Deref, DerefMut};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const MAX_UPLOADS_PER_USER: usize = 3;
+const PART_SIZE: usize = 8 * 1024 * 1024; // S3 wants every part but the last to be at least 5MB
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(3600);
+
+// A paste being uploaded a chunk at a time, nothing of it is in the database until it's finished
+struct Upload {
+    paste: Paste,
+    user_id: String,
+    staging_key: String, // Content is only copied to the paste's own key once the paste_id is ours
+    encoder: Option<StreamEncoder>, // Gone once the upload is being finished
+    hasher: Sha256,
+    pending: Vec<u8>,  // Encoded content that hasn't gone to S3 yet
+    carry: Vec<u8>,    // The start of a character split across chunks
+    received: usize,   // Where the next chunk has to start
+    stored: usize,     // Encoded content so far
+    room: Option<i64>, // What was left of the user's storage quota when the upload started
+    multipart: Option<String>,
+    parts: Vec<(i32, String)>,
+    staged: bool, // All of the content made it to the staging key
+    last_seen: Instant,
+}
+
+static UPLOADS: OnceLock<HashMap<String, Upload>> = OnceLock::new();
+fn uploads() -> &'static HashMap<String, Upload> {
+    UPLOADS.get_or_init(HashMap::new)
+}
+
+fn not_found() -> PastebinError {
+    PastebinError::NotFound(
+        "Upload not found! It may have expired or still be busy with another chunk".to_string(),
+    )
+}
+
+fn generate_upload_id() -> String {
+    let secret: [u8; 16] = rand::rng().random();
+    hex::encode(secret)
+}
+
+/// Start taking content for a paste that's been checked over but not saved yet
+pub async fn start(state: &runtime::AppState, paste: Paste, user_id: String) -> Result<String, PastebinError> {
+    let mut in_progress = 0;
+    uploads()
+        .iter_async(|_, upload| {
+            if upload.user_id == user_id {
+                in_progress += 1;
+            }
+            true
+        })
+        .await;
+    if in_progress >= MAX_UPLOADS_PER_USER {
+        return Err(PastebinError::TooMany(format!(
+            "You can only have {} uploads going at once!",
+            MAX_UPLOADS_PER_USER
+        )));
+    }
+
+    let room = paste::check_quota(state, &paste.user_id, true).await?;
+
+    let encoder = StreamEncoder::new(paste.content_encoding, state.config.compression.settings())
+        .map_err(|err| PastebinError::Internal(err.to_string()))?;
+
+    let upload_id = generate_upload_id();
+    let upload = Upload {
+        paste,
+        user_id,
+        staging_key: format!("{}uploads/{}", state.config.s3_private_prefix, upload_id),
+        encoder: Some(encoder),
+        hasher: Sha256::new(),
+        pending: Vec::new(),
+        carry: Vec::new(),
+        received: 0,
+        stored: 0,
+        room,
+        multipart: None,
+        parts: Vec::new(),
+        staged: false,
+        last_seen: Instant::now(),
+    };
+    if uploads().insert_async(upload_id.clone(), upload).await.is_err() {
+        return Err(PastebinError::Internal("Failed to start the upload, try again!".to_string()));
+    }
+
+    Ok(upload_id)
+}
+
+// An upload taken out of the map, which goes back in when this is dropped unless it's given up on,
+// so a client that goes away halfway through a request doesn't lose the whole upload
+struct Taken {
+    upload_id: String,
+    upload: Option<Upload>,
+}
+
+impl Taken {
+    fn into_inner(mut self) -> Upload {
+        self.upload.take().unwrap()
+    }
+}
+
+impl Deref for Taken {
+    type Target = Upload;
+
+    fn deref(&self) -> &Upload {
+        self.upload.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Taken {
+    fn deref_mut(&mut self) -> &mut Upload {
+        self.upload.as_mut().unwrap()
+    }
+}
+
+impl Drop for Taken {
+    fn drop(&mut self) {
+        // Whatever panicked left the upload in no state to carry on from
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(mut upload) = self.upload.take() {
+            upload.last_seen = Instant::now();
+            let _ = uploads().insert_sync(std::mem::take(&mut self.upload_id), upload);
+        }
+    }
+}
+
+// Uploads are taken out of the map while a request works on them, so one chunk goes in at a time
+async fn take(upload_id: &str, user_id: &str) -> Result<Taken, PastebinError> {
+    match uploads().remove_async(upload_id).await {
+        Some((upload_id, upload)) if upload.user_id == user_id => Ok(Taken {
+            upload_id,
+            upload: Some(upload),
+        }),
+        Some((upload_id, upload)) => {
+            let _ = uploads().insert_async(upload_id, upload).await;
+            Err(not_found())
+        }
+        None => Err(not_found()),
+    }
+}
+
+async fn discard(state: &runtime::AppState, upload: Upload) {
+    if let Some(multipart) = upload.multipart {
+        let _ = s3::abort_multipart(state, &upload.staging_key, &multipart).await;
+    }
+    if upload.staged {
+        let _ = s3::delete(state, &upload.staging_key, false).await;
+    }
+}
+
+async fn send_part(state: &runtime::AppState, upload: &mut Upload, length: usize) -> Result<(), PastebinError> {
+    let multipart = match &upload.multipart {
+        Some(multipart) => multipart.clone(),
+        None => {
+            let multipart = s3::create_multipart(
+                state,
+                &upload.staging_key,
+                &upload.paste.get_content_type(),
+                upload.paste.content_encoding.as_str(),
+                &upload.paste.title,
+                &upload.paste.tags,
+                &format!("{}.{}", upload.paste.paste_id, upload.paste.get_ext()),
+            )
+            .await
+            .map_err(|err| PastebinError::Storage(format!("Failed to upload to S3: {}", err)))?;
+            upload.multipart = Some(multipart.clone());
+            multipart
+        }
+    };
+
+    // Pending content only goes once S3 has it, so a failed part can be tried again
+    let part_number = upload.parts.len() as i32 + 1;
+    let e_tag = s3::upload_part(
+        state,
+        &upload.staging_key,
+        &multipart,
+        part_number,
+        upload.pending[..length].to_vec(),
+    )
+    .await
+    .map_err(|err| PastebinError::Storage(format!("Failed to upload to S3: {}", err)))?;
+
+    upload.parts.push((part_number, e_tag));
+    upload.pending.drain(..length);
+    Ok(())
+}
+
+/// How much of the upload we have, which is where the next chunk starts
+pub async fn status(upload_id: &str, user_id: &str) -> Result<usize, PastebinError> {
+    uploads()
+        .read_async(upload_id, |_, upload| (upload.user_id == user_id).then_some(upload.received))
+        .await
+        .flatten()
+        .ok_or_else(not_found)
+}
+
+/// Add a chunk of content at the given offset, returning where the next one starts
+pub async fn append(
+    state: &runtime::AppState,
+    upload_id: &str,
+    user_id: &str,
+    offset: usize,
+    chunk: Bytes,
+) -> Result<usize, PastebinError> {
+    if chunk.len() > MAX_CHUNK_SIZE {
+        return Err(PastebinError::TooBig(format!(
+            "Chunks can be up to {} bytes!",
+            MAX_CHUNK_SIZE
+        )));
+    }
+
+    let mut upload = take(upload_id, user_id).await?;
+    let received = upload.received;
+
+    if upload.encoder.is_none() {
+        return Err(PastebinError::Validation(
+            "This upload is already being finished!".to_string(),
+        ));
+    }
+
+    // A chunk we already have is likely a retry after a lost response, anything else has to follow on
+    if offset != received {
+        if offset + chunk.len() <= received {
+            return Ok(received);
+        }
+        return Err(PastebinError::Validation(format!(
+            "The next chunk starts at offset {}!",
+            received
+        )));
+    }
+
+    if chunk.is_empty() {
+        return Ok(received);
+    }
+
+    if received + chunk.len() > state.config.upload_max_size {
+        return Err(PastebinError::TooBig(format!(
+            "Uploads can be up to {} bytes!",
+            state.config.upload_max_size
+        )));
+    }
+
+    // Chunks may split a character, whatever's left of one waits for the next chunk
+    let carried = upload.carry.len();
+    let mut text = std::mem::take(&mut upload.carry);
+    text.extend_from_slice(&chunk);
+    let valid = match std::str::from_utf8(&text) {
+        Ok(_) => text.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => {
+            text.truncate(carried);
+            upload.carry = text;
+            return Err(PastebinError::Validation("Content has to be UTF-8 text!".to_string()));
+        }
+    };
+    upload.carry = text.split_off(valid);
+
+    // Code pastes without a language get it guessed from the first chunk, which also settles the key
+    if received == 0 {
+        if let PasteFormat::Code(None) = upload.paste.format {
+            upload.paste.format = PasteFormat::Code(highlight::detect(&String::from_utf8_lossy(&text)));
+        }
+        upload.paste.assign_s3_key(state);
+    }
+    upload.received += chunk.len();
+
+    // Crunching a big log takes a while, keep it off the async workers. The upload is kept whole
+    // in there, as nobody may be waiting on the result by the time it's done
+    let (mut upload, encoded) = match tokio::task::spawn_blocking(move || {
+        upload.hasher.update(&text);
+        let encoder = upload.encoder.as_mut().unwrap();
+        let encoded = encoder.write(&text).map(|_| encoder.take());
+        if let Ok(encoded) = &encoded {
+            upload.stored += encoded.len();
+            upload.pending.extend(encoded);
+        }
+        (upload, encoded)
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(err) => {
+            error!("Compression task failed: {}", err);
+            return Err(PastebinError::Internal(err.to_string()));
+        }
+    };
+
+    if let Err(err) = encoded {
+        error!("Failed to compress content: {}", err);
+        discard(state, upload.into_inner()).await;
+        return Err(PastebinError::Internal(err.to_string()));
+    }
+
+    // There's no making it fit by sending the rest, so the upload goes
+    if upload.room.is_some_and(|room| upload.stored as i64 > room) {
+        discard(state, upload.into_inner()).await;
+        return Err(paste::over_quota());
+    }
+
+    // The chunk is ours now, so S3 trouble leaves the rest pending for the next chunk or the finish
+    let received = upload.received;
+    while upload.pending.len() >= PART_SIZE {
+        send_part(state, &mut upload, PART_SIZE).await?;
+    }
+
+    Ok(received)
+}
+
+/// Put the last of the content in storage and save the paste, returning its paste_id
+pub async fn finish(state: &runtime::AppState, upload_id: &str, user_id: &str) -> Result<String, PastebinError> {
+    let mut upload = take(upload_id, user_id).await?;
+
+    if upload.received == 0 {
+        return Err(PastebinError::Validation("Content is empty!".to_string()));
+    }
+
+    if !upload.carry.is_empty() {
+        return Err(PastebinError::Validation(
+            "Content stops partway through a character!".to_string(),
+        ));
+    }
+
+    // Finishing may be tried again if S3 acts up, the encoder is only finished the first time
+    if upload.encoder.is_some() {
+        let (upload_back, finished) = match tokio::task::spawn_blocking(move || {
+            let finished = upload.encoder.take().unwrap().finish().map(|encoded| {
+                upload.stored += encoded.len();
+                upload.pending.extend(encoded);
+            });
+            (upload, finished)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Compression task failed: {}", err);
+                return Err(PastebinError::Internal(err.to_string()));
+            }
+        };
+        upload = upload_back;
+
+        if let Err(err) = finished {
+            error!("Failed to finish compressing upload: {}", err);
+            discard(state, upload.into_inner()).await;
+            return Err(PastebinError::Internal(err.to_string()));
+        }
+    }
+
+    if !upload.staged {
+        match upload.multipart.clone() {
+            // Small enough to have never needed more than the one part
+            None => s3::upload(
+                state,
+                &upload.staging_key,
+                upload.pending.clone(),
+                &upload.paste.get_content_type(),
+                upload.paste.content_encoding.as_str(),
+                &upload.paste.title,
+                &upload.paste.tags,
+                &format!("{}.{}", upload.paste.paste_id, upload.paste.get_ext()),
+                false,
+            )
+            .await
+            .map_err(|err| PastebinError::Storage(format!("Failed to upload to S3: {}", err)))?,
+            Some(multipart) => {
+                while !upload.pending.is_empty() {
+                    let length = upload.pending.len().min(PART_SIZE);
+                    send_part(state, &mut upload, length).await?;
+                }
+                s3::complete_multipart(state, &upload.staging_key, &multipart, &upload.parts)
+                    .await
+                    .map_err(|err| PastebinError::Storage(format!("Failed to upload to S3: {}", err)))?;
+            }
+        }
+        upload.multipart = None;
+        upload.staged = true;
+    }
+
+    let content_hash = hex::encode(upload.hasher.clone().finalize());
+    let saved = upload
+        .paste
+        .save_upload(state, &upload.staging_key, upload.stored as i32, &content_hash)
+        .await;
+
+    // Either the paste has its own copy of the content by now or it never will
+    discard(state, upload.into_inner()).await;
+    saved
+}
+
+/// Give up on an upload and whatever has been sent of it
+pub async fn abort(state: &runtime::AppState, upload_id: &str, user_id: &str) -> Result<(), PastebinError> {
+    let upload = take(upload_id, user_id).await?;
+    discard(state, upload.into_inner()).await;
+    Ok(())
+}
+
+pub async fn reap_stale(state: &runtime::AppState, do_sleep: bool) {
+    loop {
+        if do_sleep {
+            sleep(Duration::from_secs(state.config.reap_expired_interval)).await;
+        }
+
+        let mut stale = Vec::new();
+        uploads()
+            .iter_async(|upload_id, upload| {
+                if upload.last_seen.elapsed() > UPLOAD_TIMEOUT {
+                    stale.push(upload_id.clone());
+                }
+                true
+            })
+            .await;
+
+        if !stale.is_empty() {
+            info!("About to reap {} stale upload(s)..", stale.len());
+        }
+
+        for upload_id in stale {
+            if let Some((_, upload)) = uploads()
+                .remove_if_async(&upload_id, |upload| upload.last_seen.elapsed() > UPLOAD_TIMEOUT)
+                .await
+            {
+                discard(state, upload).await;
+            }
+        }
+
+        if !do_sleep {
+            break;
+        }
+    }
+}
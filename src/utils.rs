@@ -1,15 +1,16 @@
-use crate::{forms::ValidDestination, runtime};
+use crate::{forms::ValidDestination, paste::PasteFormat, runtime};
+use crate::cloudflare;
+use crate::compression::{self, Encoding};
 use crate::templates;
 use crate::errors::PastebinError;
 use axum::{
     extract::{Request, State},
-    http::{HeaderValue, StatusCode},
+    http::{header::ACCEPT_ENCODING, HeaderMap, HeaderValue, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use brotli::CompressorWriter;
 use sha2::{Sha256, Digest};
-use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use tower_cookies::{cookie::SameSite, Cookie, Cookies};
 use tracing::error;
@@ -103,7 +104,7 @@ pub async fn csp(
             "frame-src blob: {} https://challenges.cloudflare.com",
             s3_bucket_url
         ),
-        format!("img-src data: {}", static_domain),
+        format!("img-src data: {} {}", static_domain, s3_bucket_url),
         format!(
             "script-src {} https://challenges.cloudflare.com https://static.cloudflareinsights.com 'sha256-Kh7z5uN5f6WzJriXlPY/hfklAtevSrublssQEZyvSck='",
             static_domain
@@ -112,6 +113,11 @@ pub async fn csp(
         format!("upgrade-insecure-requests"),
     ];
 
+    // Handlers serving paste content directly set their own, stricter policy
+    if response.headers().contains_key("Content-Security-Policy") {
+        return Ok(response);
+    }
+
     if let Ok(csp_header) = HeaderValue::from_str(&policy.join("; ").to_string()) {
         response
             .headers_mut()
@@ -126,34 +132,95 @@ pub fn not_found_response() -> Response {
     (StatusCode::NOT_FOUND, templates::HtmlTemplate(template)).into_response()
 }
 
-// Compress content using brotli, returning the compressed content and the content encoding
-pub async fn compress(content: &str, s3_content: &mut Vec<u8>, destination: &ValidDestination) -> Result<String, PastebinError> {
-    s3_content.clear();
+// Pick an encoding for content of this size and format as configured
+pub fn choose_encoding(state: &runtime::AppState, size: usize, destination: &ValidDestination, format: &PasteFormat) -> Encoding {
+    let settings = &state.config.compression;
 
-    // Avoid compression if the content is too small
-    if content.len() < 1024 {
-        s3_content.extend_from_slice(content.as_bytes());
-        return Ok("identity".to_string());
+    if let PasteFormat::Encrypted = format {
+        // Avoid compression if the content is ciphertext, it won't shrink anyway
+        Encoding::Identity
+    } else if size < settings.min_size {
+        // Avoid compression if the content is too small
+        Encoding::Identity
+    } else if destination == &ValidDestination::GDrive {
+        // Avoid compression if the destination is GDrive
+        Encoding::Identity
+    } else {
+        settings.formats.get(format.spec().key).copied().unwrap_or(settings.encoding)
     }
+}
 
-    // Avoid compression if the destination is GDrive
-    if destination == &ValidDestination::GDrive {
-        s3_content.extend_from_slice(content.as_bytes());
-        return Ok("identity".to_string());
+// Compress content as configured, returning the encoded content along with its encoding
+pub async fn compress(state: &runtime::AppState, content: &str, destination: &ValidDestination, format: &PasteFormat) -> Result<(Vec<u8>, Encoding), PastebinError> {
+    let encoding = choose_encoding(state, content.len(), destination, format);
+
+    if encoding == Encoding::Identity {
+        return Ok((content.as_bytes().to_vec(), encoding));
     }
 
-    let mut encoder = CompressorWriter::new(s3_content, 4096, 6, 22);
-    if let Err(err) = encoder.write_all(content.as_bytes()) {
-        error!("Failed to write compressed content: {}", err);
-        return Err(PastebinError::Internal(err.to_string()));
-    };
+    // Crunching a big log takes a while, keep it off the async workers
+    let settings = state.config.compression.settings();
+    let content = content.as_bytes().to_vec();
+    match tokio::task::spawn_blocking(move || compression::compress(&content, encoding, settings)).await {
+        Ok(Ok(s3_content)) => Ok((s3_content, encoding)),
+        Ok(Err(err)) => {
+            error!("Failed to compress content: {}", err);
+            Err(PastebinError::Internal(err.to_string()))
+        }
+        Err(err) => {
+            error!("Compression task failed: {}", err);
+            Err(PastebinError::Internal(err.to_string()))
+        }
+    }
+}
+
+// Check whether the client is happy to take content in this encoding as is
+pub fn accepts_encoding(headers: &HeaderMap, encoding: Encoding) -> bool {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            parts.next() == Some(encoding.as_str()) && !parts.any(|param| param.replace(' ', "") == "q=0")
+        })
+}
 
-    if let Err(err) = encoder.flush() {
-        error!("Failed to flush compress content: {}", err);
-        return Err(PastebinError::Internal(err.to_string()));
+// Decompress content fetched back from storage
+pub async fn decompress(content: Vec<u8>, encoding: Encoding) -> Result<String, PastebinError> {
+    let content = if encoding == Encoding::Identity {
+        content
+    } else {
+        match tokio::task::spawn_blocking(move || compression::decompress(&content, encoding)).await {
+            Ok(Ok(content)) => content,
+            Ok(Err(err)) => {
+                error!("Failed to decompress content: {}", err);
+                return Err(PastebinError::Internal(err.to_string()));
+            }
+            Err(err) => {
+                error!("Decompression task failed: {}", err);
+                return Err(PastebinError::Internal(err.to_string()));
+            }
+        }
     };
 
-    Ok("br".to_string())
+    String::from_utf8(content).map_err(|err| PastebinError::Internal(err.to_string()))
+}
+
+// Sizes the way people read them, 1.5 MB rather than 1572864
+pub fn human_size(bytes: i64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["bytes", "KB", "MB"] {
+        if size < 1024.0 {
+            return match unit {
+                "bytes" => format!("{} bytes", bytes),
+                _ => format!("{:.1} {}", size, unit),
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} GB", size)
 }
 
 pub fn get_cookie_name(state: &Arc<runtime::AppState>, name: &str) -> String {
@@ -186,6 +253,32 @@ pub fn build_app_cookie<'a>(state: &Arc<runtime::AppState>, name: String, value:
         .into()
 }
 
+// The visitor's address. Our nginx passes on the address it saw in X-Real-IP, and only when that's
+// one of Cloudflare's edges can we believe what CF-Connecting-IP says about who's behind it
+pub fn client_ip(state: &runtime::AppState, headers: &HeaderMap, addr: SocketAddr) -> String {
+    let header_ip = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|ip| ip.to_str().ok())
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+    };
+
+    let mut ip = addr.ip();
+    if ip.is_loopback() {
+        if let Some(real_ip) = header_ip("X-Real-IP") {
+            ip = real_ip;
+        }
+    }
+
+    if state.config.cloudflare_enabled && cloudflare::is_edge(ip) {
+        if let Some(visitor_ip) = header_ip("CF-Connecting-IP") {
+            ip = visitor_ip;
+        }
+    }
+
+    ip.to_string()
+}
+
 pub fn get_user_id(
     state: &Arc<runtime::AppState>,
     cookies: &Cookies,
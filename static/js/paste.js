@@ -40,6 +40,12 @@
         let data = new FormData(e.target);
         editFieldSet.setAttribute("disabled", true);
 
+        // Don't make a new revision if the content wasn't touched
+        let editContent = document.getElementById("edit-content");
+        if (editContent !== null && editContent.value == editContent.defaultValue) {
+          data.delete("content");
+        }
+
         // Encode the form data using URLSearchParams
         const encodedData = new URLSearchParams(data);
 
@@ -73,6 +79,84 @@
       });
     }
 
+    // Fork button
+    let forkForm = document.getElementById("fork-form");
+    if (forkForm !== null) {
+      let forkBtn = document.getElementById("fork-btn");
+      let forkWidgetId = undefined;
+
+      let forkSubmit = function (token) {
+        let data = new FormData(forkForm);
+        data.set("token", token);
+
+        // Encode the form data using URLSearchParams
+        const encodedData = new URLSearchParams(data);
+
+        fetch(forkForm.getAttribute("action"), {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/x-www-form-urlencoded",
+            "X-Requested-With": "XMLHttpRequest",
+          },
+          body: encodedData.toString(),
+        })
+          .then((response) => {
+            if (response.ok) {
+              return response.text();
+            } else {
+              alert(
+                `Oops, we couldn't fork this paste :( The following was encountered:\n\n${response.status}: ${response.statusText}`,
+              );
+              throw "-flails-";
+            }
+          })
+          .then((result) => {
+            // Encrypted pastes need their key to come along
+            location.href = "/pastebin/" + result + location.hash;
+          })
+          .catch((error) => {
+            if (error != "-flails-") {
+              alert(
+                "Oops, we couldn't fork this paste :( Maybe the network pipes aren't up?",
+              );
+            }
+
+            if (forkWidgetId != undefined) {
+              turnstile.remove(forkWidgetId);
+              forkWidgetId = undefined;
+            }
+            forkBtn.removeAttribute("disabled");
+          });
+      };
+
+      let forkCaptcha = function () {
+        forkWidgetId = turnstile.render("#cf-turnstile", {
+          sitekey: document.getElementById("recaptcha-key").value,
+          action: "paste",
+          theme: "dark",
+          callback: forkSubmit,
+        });
+      };
+
+      forkForm.addEventListener("submit", (e) => {
+        e.preventDefault();
+
+        forkBtn.setAttribute("disabled", true);
+        if (document.getElementById("recaptcha-key").value.length == 0) {
+          forkSubmit("");
+        } else if (window.turnstile !== undefined) {
+          forkCaptcha();
+        } else {
+          // Only bring in the captcha once someone actually wants to fork
+          window.forkCaptcha = forkCaptcha;
+          let script = document.createElement("script");
+          script.src =
+            "https://challenges.cloudflare.com/turnstile/v0/api.js?render=explicit&onload=forkCaptcha";
+          document.head.appendChild(script);
+        }
+      });
+    }
+
     // Fancy delete button
     document
       .getElementById("delete-form")
@@ -136,6 +220,122 @@
       return output;
     };
 
+    let fromBase64 = function (input) {
+      input = input.replace(/-/g, "+").replace(/_/g, "/");
+      return Uint8Array.from(atob(input), (c) => c.charCodeAt(0));
+    };
+
+    // Decrypt content in the browser with the key from the URL fragment
+    let decryptContent = async function (result) {
+      let rawKey = location.hash.substring(1);
+      if (rawKey.length == 0) {
+        throw "-nokey-";
+      }
+
+      const key = await crypto.subtle.importKey(
+        "raw",
+        fromBase64(rawKey),
+        "AES-GCM",
+        false,
+        ["decrypt"],
+      );
+      const payload = fromBase64(result.trim());
+      const plaintext = await crypto.subtle.decrypt(
+        { name: "AES-GCM", iv: payload.slice(0, 12) },
+        key,
+        payload.slice(12),
+      );
+      return JSON.parse(new TextDecoder().decode(plaintext));
+    };
+
+    // Render content in whichever format it came in
+    let renderContent = async function (result, format) {
+      format = format || document.getElementById("format").value;
+
+      if (format == "enc") {
+        let envelope;
+        try {
+          envelope = await decryptContent(result);
+        } catch (error) {
+          console.log(error);
+          document.getElementById("decrypt-failure").classList.remove("d-none");
+          document.getElementById("loader").classList.add("d-none");
+          return;
+        }
+
+        await renderContent(envelope.content, envelope.format);
+      } else if (format == "log") {
+        let output = document.getElementById("content-terminal");
+        output.classList.remove("d-none");
+
+        result = escape_html(result);
+        let lines = result.split(/\r\n|\n/g);
+        let txt = lines.join("<br>");
+
+        const { AnsiUp } = await import("/static/vendor/js/ansi_up.js.br");
+        let ansi_up = new AnsiUp();
+        ansi_up.escape_html = false;
+        output.innerHTML = ansi_up.ansi_to_html(txt);
+
+        document.getElementById("loader").classList.add("d-none");
+      } else if (format == "md" && document.getElementById("content-markdown") !== null) {
+        // Markdown is rendered on our side, there's nothing left to do here
+        document.getElementById("loader").classList.add("d-none");
+      } else if (format.startsWith("code") && document.getElementById("content-code") !== null) {
+        // So is code
+        document.getElementById("loader").classList.add("d-none");
+      } else if (format == "html") {
+        document.getElementById("content-frame").srcdoc = result; // This because Safari doesn't support blobs
+        document.getElementById("content-frame").classList.remove("d-none");
+        document.getElementById("loader").classList.add("d-none");
+      } else {
+        document.getElementById("content-text").classList.remove("d-none");
+
+        result = escape_html(result);
+        document.getElementById("content-text").innerHTML = result.replace(
+          /^(.*)$/gm,
+          '<span class="line">$1</span>',
+        );
+
+        document.getElementById("loader").classList.add("d-none");
+      }
+    };
+
+    // Bundle tabs fetch their file the first time they're opened
+    document.querySelectorAll("#file-tabs button").forEach((tab) => {
+      tab.addEventListener("shown.bs.tab", () => {
+        let pane = document.querySelector(tab.dataset.bsTarget);
+        let fileURL = pane.dataset.fileUrl;
+        if (fileURL === undefined) {
+          return;
+        }
+        delete pane.dataset.fileUrl;
+
+        fetch(fileURL, {
+          headers: {
+            "X-Requested-With": "XMLHttpRequest",
+          },
+        })
+          .then((response) => {
+            if (response.ok) {
+              return response.text();
+            } else {
+              throw `${response.status}: ${response.statusText}`;
+            }
+          })
+          .then((result) => {
+            pane.innerHTML = result;
+          })
+          .catch((error) => {
+            // Let the next click have another go
+            pane.dataset.fileUrl = fileURL;
+            let loader = pane.querySelector(".file-loader");
+            loader.classList.replace("text-light", "text-danger");
+            loader.textContent = `Meep! I couldn't get this file -flails- (${error})`;
+          });
+      });
+    });
+
     // Fancy content fetch
     let fetchContent = function (contentURL) {
       fetch(contentURL, {
@@ -157,38 +357,15 @@
             throw "-flails-";
           }
         })
-        .then(async (result) => {
-          let format = document.getElementById("format").value;
-
-          if (format == "log") {
-            let output = document.getElementById("content-terminal");
-            output.classList.remove("d-none");
-
-            result = escape_html(result);
-            let lines = result.split(/\r\n|\n/g);
-            let txt = lines.join("<br>");
-
-            const { AnsiUp } = await import("/static/vendor/js/ansi_up.js.br");
-            let ansi_up = new AnsiUp();
-            ansi_up.escape_html = false;
-            output.innerHTML = ansi_up.ansi_to_html(txt);
-
-            document.getElementById("loader").classList.add("d-none");
-          } else if (format == "html") {
-            document.getElementById("content-frame").srcdoc = result; // This because Safari doesn't support blobs
-            document.getElementById("content-frame").classList.remove("d-none");
-            document.getElementById("loader").classList.add("d-none");
-          } else {
-            document.getElementById("content-text").classList.remove("d-none");
-
-            result = escape_html(result);
-            document.getElementById("content-text").innerHTML = result.replace(
-              /^(.*)$/gm,
-              '<span class="line">$1</span>',
-            );
-
-            document.getElementById("loader").classList.add("d-none");
+        .then((result) => {
+          // Prefill the edit form with what we've got
+          let editContent = document.getElementById("edit-content");
+          if (editContent !== null) {
+            editContent.defaultValue = result;
+            editContent.removeAttribute("placeholder");
           }
+
+          return renderContent(result);
         })
         .catch((error) => {
           if (error != "-flails-") {
@@ -205,7 +382,10 @@
     document.getElementById("loader").classList.remove("d-none");
     let pasteID = document.getElementById("paste-id").value;
     let contentURL = document.getElementById("content-url").value;
-    if (document.querySelectorAll("#driveHosted").length > 0) {
+    let contentInline = document.getElementById("content-inline");
+    if (contentInline !== null) {
+      renderContent(contentInline.value);
+    } else if (document.querySelectorAll("#driveHosted").length > 0) {
       fetch("/pastebinc/" + pasteID + "/content/link")
         .then((response) => {
           if (response.ok) {
@@ -21,11 +21,65 @@ function HandleGAuthComplete(result) {
   }
 }
 
-function fancyFormSubmit(token) {
+function toBase64(bytes) {
+  let binary = "";
+  bytes.forEach((b) => {
+    binary += String.fromCharCode(b);
+  });
+  return btoa(binary);
+}
+
+// Encrypt content in the browser, the key only ever lives in the URL fragment
+async function encryptContent(data) {
+  const key = await crypto.subtle.generateKey(
+    { name: "AES-GCM", length: 256 },
+    true,
+    ["encrypt"],
+  );
+  const iv = crypto.getRandomValues(new Uint8Array(12));
+  const envelope = JSON.stringify({
+    format: data.get("format"),
+    content: data.get("content"),
+  });
+  const ciphertext = await crypto.subtle.encrypt(
+    { name: "AES-GCM", iv: iv },
+    key,
+    new TextEncoder().encode(envelope),
+  );
+
+  let payload = new Uint8Array(iv.length + ciphertext.byteLength);
+  payload.set(iv);
+  payload.set(new Uint8Array(ciphertext), iv.length);
+  data.set("content", toBase64(payload));
+  data.set("format", "enc");
+
+  const rawKey = new Uint8Array(await crypto.subtle.exportKey("raw", key));
+  return toBase64(rawKey).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}
+
+async function fancyFormSubmit(token) {
   let form = document.getElementById("pasteform");
   let data = new FormData(form);
   data.set("token", token);
 
+  let fragment = "";
+  if (document.getElementById("encrypt").checked) {
+    try {
+      fragment = "#" + (await encryptContent(data));
+    } catch (error) {
+      console.log(error);
+      alert("Oops, we couldn't encrypt your paste :( Maybe your browser doesn't support it?");
+      document.getElementById("pastebtn-loading").classList.add("d-none");
+      document.getElementById("pastebtn-ready").classList.remove("d-none");
+      if (turnstileWidgetId != undefined) {
+        turnstile.remove(turnstileWidgetId);
+        turnstileWidgetId = undefined;
+      }
+      postInProgress = false;
+      return;
+    }
+  }
+
   // Encode the form data using URLSearchParams
   const encodedData = new URLSearchParams(data);
 
@@ -51,7 +105,7 @@ function fancyFormSubmit(token) {
       }
     })
     .then((result) => {
-      location.replace(result);
+      location.replace(result + fragment);
     })
     .catch((error) => {
       if (error != "-flails-") {
@@ -57,12 +57,20 @@
                 item.title = item.paste_id;
               }
 
+              let language = "";
+              if (item.language) {
+                let badge = document.createElement("span");
+                badge.className = "badge bg-dark border border-secondary ms-1";
+                badge.textContent = item.language;
+                language = ` ${badge.outerHTML}`;
+              }
+
               let date = new Date(item.date);
               let cleanTitle = document.createElement("span");
               cleanTitle.textContent = item.title;
               let row = `
               <tr>
-                <td><a class="text-decoration-none" href="/pastebin/${item.paste_id}">${cleanTitle.innerHTML}</a></td>
+                <td><a class="text-decoration-none" href="/pastebin/${item.paste_id}">${cleanTitle.innerHTML}</a>${language}</td>
                 <td title="${date.toTimeString()}">${date.toLocaleString()}</td>
                 <td>${tags.join("")}</td>
                 <td class="text-end">${item.views}</td>